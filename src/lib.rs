@@ -23,46 +23,52 @@
  */
 
 // Rust configuration section
-// libretro cores with threading require many experimental Rust features.
-// Leave all this unchanged.
-
-#![crate_type = "dylib"]
-// All these features are required for native OS concurrency and panic handling
-// without the runtime.
-#![feature(macro_rules, globs, lang_items, unsafe_destructor, linkage, phase, asm)]
-#![no_std]
-// core must be loaded with plugin phase for panic handling
-#[phase(plugin, link)]
-extern crate core;
-extern crate alloc;
-extern crate collections;
-extern crate libc;
-
-use core::intrinsics::transmute;
-use core::prelude::*;
+// The wrapper is built on std threading and panic handling, and keeps its
+// callbacks and core state in static muts. Leave all this unchanged.
+
+#![allow(non_upper_case_globals)]
+
+use std::ptr;
+use std::slice;
 use rust_wrapper::*;
 
-#[macro_escape]
+#[macro_use]
 pub mod rust_wrapper;
 
-// std must be declared even with #![no_std] for panic handling
-mod std { pub use core::fmt; }
-
 // Libretro core configuration section.
 // All values must be set for the core to initialize correctly.
-// All configuration strings must be plain ASCII.
+// Names, versions, extensions and keys must be plain ASCII. Descriptions,
+// such as those of ENV_VARS, may be UTF-8.
     
 // Name and version number, for display in the frontend GUI.
-CORE_NAME!("Example Core")
-CORE_VERSION!("0.0.1")
+CORE_NAME!("Example Core");
+CORE_VERSION!("0.0.1");
 
 // Does the core run without the frontend loading content for it?
 const NO_CONTENT: bool = true;
 
 // List of valid extensions for content, separated by pipes. For example:
-// VALID_EXTENSIONS!("bin|iso")
+// VALID_EXTENSIONS!("bin|iso");
+// Add m3u to load playlists of disc images, see core_load_game().
 // If NO_CONTENT is true then VALID_EXTENSIONS is ignored.
-VALID_EXTENSIONS!("")
+VALID_EXTENSIONS!("");
+
+// Subsystems load several pieces of content together, such as a base game
+// and an expansion, or two games linked for two players. The frontend offers
+// them alongside normal content loading, and passes the content to
+// core_load_subsystem(). For example:
+// const SUBSYSTEMS: &[Subsystem] = &[
+//     Subsystem { desc: "Base game and expansion",
+//                 ident: "expansion",
+//                 id: 1,
+//                 content: &[
+//                     SubsystemContent { desc: "Base game", extensions: "bin",
+//                                        required: true, need_fullpath: false },
+//                     SubsystemContent { desc: "Expansion", extensions: "exp",
+//                                        required: false, need_fullpath: false },
+//                 ], },
+//    ];
+const SUBSYSTEMS: &[Subsystem] = &[];
 
 // Core screen size in pixels.
 // Frontends provide various options for upscaling if this is lower than the
@@ -87,18 +93,27 @@ const AV_PIXEL_ASPECT: f32 = 1.0;
 
 // Libretro is designed around fixed frame rate cores. For maximum compatibility
 // with various display refresh rates, rust-libretro uses threaded rendering,
-// where a snapshot of video related state is saved after a fixed number of core
-// logic updates and used to render a video frame chronously.
+// where a snapshot of video related state is saved after a number of core logic
+// updates and used to render a video frame chronously.
 //
-// Core logic rate is one of three supported values:
-// LogicRate60 (60Hz)
-// LogicRate120 (120Hz)
-// LogicRate720 (720Hz)
+// Core logic rate may be any whole number of updates per second, for example
+// 60, 120 or 720. PAL content runs at PAL_CORE_LOGIC_RATE instead, see REGION
+// below.
 //
 // rust-libretro automatically generates a core option to allow the user to
-// choose a frame rate from a selection of integer divisions of the core
-// logic rate. The default is always 60fps for maximum compatibility with common
-// 60Hz refresh rate displays. 
+// choose a frame rate from every whole number frame rate between 24 and 240fps
+// that divides the core logic rate, plus common display refresh rates such as
+// 59.94, 75 or 165Hz. When the frame rate doesn't divide the core logic rate,
+// the number of core logic updates per frame varies slightly, for example
+// alternating between 12 and 13 for a 720Hz core at 59.94fps, so the game runs
+// at the correct speed. The default, auto, picks the one closest to the refresh
+// rate the frontend is targeting, or closest to 60fps if the frontend doesn't
+// report one, for maximum compatibility with common 60Hz refresh rate displays.
+//
+// A second core option switches frame pacing to real time, where each frame
+// runs as many core logic updates as the frontend reports time has passed.
+// This suits displays that don't hold an exact refresh rate, but input
+// recordings made this way will not replay identically.
 //
 // Please choose the highest core logic rate possible for your target hardware.
 // 720Hz core logic rate has excellent compatibility with all common displays,
@@ -113,13 +128,38 @@ const AV_PIXEL_ASPECT: f32 = 1.0;
 // could pose a risk to photosensitive epileptics. Use alpha blending, or if you
 // need only one layer of transparency, dithering may also be acceptable.
 //
-// Future versions of libretro will include support for automatic configuration
-// of the frame rate, support for tuning of the video latency to trade off
-// latency with performance, support for polling input at the full core logic
-// rate to minimize control latency and jitter, and compatibility of input
-// recordings between all frame rates. Choosing a 720Hz core logic rate will give
-// you the maximum benefit from these improvements.
-const CORE_LOGIC_RATE: CoreLogicRate = LogicRate720;
+// Input is polled once per frame by default. The automatic input polling core
+// option can poll again before every core logic update, which gives finer
+// grained input on frontends that read the hardware on every poll.
+//
+// The automatic input recording core option records the input of every core
+// logic update to a movie file in the frontend's save directory, and plays it
+// back through InputState::poll. Recordings play back identically at every
+// frame rate, so long as core_run() only depends on its input.
+//
+// Future versions of libretro will include support for tuning of the video
+// latency to trade off latency with performance. Choosing a 720Hz core logic
+// rate will give you the maximum benefit from these improvements.
+const CORE_LOGIC_RATE: u32 = 720;
+
+// The region the core runs in, returned by retro_get_region(). Either
+// RegionSource::Fixed(Region::Ntsc) or RegionSource::Fixed(Region::Pal),
+// RegionSource::Content for the region core_load_game() reports with
+// set_region(), or RegionSource::Option for a core option that defaults to
+// the content's region. PAL content runs core logic at PAL_CORE_LOGIC_RATE,
+// which must be a multiple of 50, so games run at the slower PAL speed, and
+// the frame rate falls back to 50fps. 600Hz suits 50Hz displays as 720Hz suits
+// 60Hz ones, and still runs exactly 10 updates per frame at 60fps. Check
+// region() in core_run() if the game must behave differently in PAL.
+const REGION: RegionSource = RegionSource::Fixed(Region::Ntsc);
+const PAL_CORE_LOGIC_RATE: u32 = 600;
+
+// Seed for the random number generator returned by rng(). It is seeded with
+// this whenever content is loaded or the core is reset, so a game plays out
+// the same way from the same input. Call seed_rng() to choose a different
+// seed, for example one picked by the player. Never seed from the clock, as
+// input recordings would no longer play back correctly.
+const RANDOM_SEED: u64 = 0x2014_0425;
 
 // Audio sampling rate, in Hertz. The frontend is responsible for resampling
 // audio to a rate supported by the hardware, so unusual sampling rates will not
@@ -135,37 +175,124 @@ const COLOR_DEPTH_32: bool = false;
 
 // You may specify environment variables as an array of EnvVar
 // structs. eg.:
-const ENV_VARS: &'static [EnvVar] = [
-    EnvVar { key: "cheats",
-             desc: "Enable cheats",
+const ENV_VARS: &[EnvVar] = &[
+    EnvVar { key: "difficulty",
+             desc: "Difficulty",
              // first element of values is the default
-             values: ["no", "yes"], },
+             values: &["normal", "easy", "hard"], },
    ];
 // These will be appended to the list of automatically provided environment
 // variables. Leave the list empty if you do not want custom environment
 // variables. eg.:
-// const ENV_VARS: &'static[EnvVar] = [];
+// const ENV_VARS: &[EnvVar] = &[];
+
+// Translations of the core's text, one string table per language, built into
+// the core with include_str!. For example:
+// const STRING_TABLES: &[(Language, &str)] = &[
+//     (Language::French, include_str!("lang/fr.txt")),
+//     (Language::Japanese, include_str!("lang/ja.txt")),
+//    ];
+// Each line of a table is "key = value", where the key is usually the English
+// text, and lines starting with # are comments. tr("Game Over") returns the
+// text for the frontend's language, or for the language core option
+// rust-libretro generates when there is more than one table, falling back to
+// the key. ENV_VARS descriptions are translated the same way. Draw the text
+// with draw_text() and a BitmapFont that has glyphs for the languages listed.
+const STRING_TABLES: &[(Language, &str)] = &[];
+
+// Log with the log_debug!, log_info!, log_warn! and log_error! macros, which
+// take format arguments like println!. rust-libretro generates a core option
+// to choose the lowest level logged, and if you list module paths here, a
+// second option to show debug and info messages from only one of them. For
+// example:
+// const LOG_MODULES: &[&str] = &["rust_libretro::physics"];
+// Each call site logs at most a few messages per second, so it is safe to log
+// from core_run().
+const LOG_MODULES: &[&str] = &[];
+
+// Messages for the player, such as failing to load content, can be shown on
+// screen with notify(text, seconds) from core_run() or the other functions
+// called on the frontend's thread.
+
+// Time a block of code with perf_scope!("physics"), which registers a
+// performance counter with the frontend, if it has them. The automatic
+// performance report core option logs every frame how long core_run(),
+// snapshot_video(), render_video() and each perf_scope! took.
 
 
 
 // You must implement several functions that will be automatically called by
 // rust-libretro.
 //
+// core_load_game() is called when content is loaded, before the first call to
+// core_run(), with the content's path and data, both empty when NO_CONTENT
+// lets the core start without any. The data is only valid during the call,
+// so copy what you need. Return false if the content can't be used. Content
+// whose region depends on its header can report it here with set_region().
+// Register any memory the frontend should see here with register_memory(),
+// for example battery backed save RAM, which the frontend then loads and
+// saves for you, or system RAM for memory viewers. The regions must be static
+// muts, or otherwise live for as long as the content is loaded. Cores
+// emulating a machine can also describe where its memory sits in the
+// machine's address space with map_memory(), for achievements and cheat
+// searches. For games on several discs, call set_disk_control() with a
+// DiskList of the image paths, or your own DiskControl, so the player can
+// swap discs from the frontend. Content that is an M3U playlist of images
+// gets a DiskList automatically. Check inserted_disk() in core_run() to see
+// which image is in the tray.
+// BIOS images and other files the core needs can be read from the frontend's
+// system directory with read_system_file(), and extra save files, such as
+// high scores, kept in its save directory with read_save_file() and
+// write_save_file(). paths() gives the directories themselves.
+// HighScores::load("arcade", 10) loads a ready made high score table, saved
+// each time a score is add()ed, which leaves out scores made with cheats.
+// username() gives the player's name in the frontend, if it has one, to start
+// name entry from.
+pub fn core_load_game(_content: &GameContent) -> bool
+{
+    true
+}
+
+// core_cheat() is offered every cheat code the frontend sets, so cores can
+// support codes only their game understands, such as "infinite lives". Return
+// a closure that applies the cheat to return Some, and rust-libretro runs it
+// after every logic update while the cheat is enabled. Return None for codes
+// rust-libretro should read as memory codes, which write values to the
+// addresses of the memory map or system RAM. See cheat.rs for their format.
+pub fn core_cheat(_code: &str) -> Option<CoreCheat>
+{
+    None
+}
+
+// core_load_subsystem() is called instead of core_load_game() when the
+// frontend loads content with one of SUBSYSTEMS. The content comes in the
+// order the subsystem lists it, with content.kind telling which part of the
+// subsystem each is. Optional content that was left out has no path or data.
+// Return false if the content can't be used.
+pub fn core_load_subsystem(_subsystem: &Subsystem, _content: &[Content]) -> bool
+{
+    false
+}
+
 // Every core must implement core_run(). You can poll input here with
-// InputState::poll(playernum) and update the core state accordingly. All state
-// change must be deterministic across all platforms, so be careful with
-// threading and floating point math. See
+// InputState::poll(playernum) and update the core state accordingly, and
+// tick_time() tells you which logic update this is and when its input was
+// polled, in updates and microseconds of game time. All state change must be
+// deterministic across all platforms, so be careful with threading and
+// floating point math. The fixed point types, such as Q24_8 and Q16_16, with
+// their Vec2 and Vec3 vectors and Angle trigonometry, give identical results
+// on every platform. If you do need floats, see
 // http://randomascii.wordpress.com/2013/07/16/floating-point-determinism/
-// for advice on using floats.
+// for advice.
 pub fn core_run()
 {
     // libretro v1 does not include user data pointers, so all state needs
     // to be stored in static muts. So long as you never call core_run() yourself
     // it is safe to convert them to owned data, because the libretro API
     // requires this function to be called from a single thread.
-    let g = &mut unsafe {mem_as_mut_slice::<GState>(transmute(&g_state), 1)}[0];
+    let g = unsafe { &mut *ptr::addr_of_mut!(g_state) };
 
-    g.frame = g.frame + 1;
+    g.frame += 1;
         
     let playernum = 0;
     // InputState::poll returns a struct than can be indexed with the
@@ -180,42 +307,43 @@ pub fn core_run()
     
     if input[PadA].pressed && !g.old_a
     {
-        g.gobj[g.gobj_idx as uint]=GObj{x: g.x as i32, y: g.y as i32, dx: 0, dy: 0};
-        g.gobj_idx = g.gobj_idx + 1;
+        g.gobj[g.gobj_idx as usize]=GObj{x: g.x, y: g.y, dx: Q24_8::ZERO, dy: Q24_8::ZERO};
+        g.gobj_idx += 1;
     }
     if g.gobj_idx == 256 {g.gobj_idx = 255;}
     g.old_a = input[PadA].pressed;
     
-    if (input[PadUp].pressed) && (g.y > 0) {
-        g.y = g.y - 48;
+    // Pixels per core logic update
+    const SPEED: Q24_8 = Q24_8::from_ratio(3, 16);
+
+    if (input[PadUp].pressed) && (g.y > Q24_8::ZERO) {
+        g.y -= SPEED;
     }
     
-    if (input[PadDown].pressed) && ((g.y) < ((AV_SCREEN_HEIGHT * 256) - 256)) {
-        g.y = g.y + 48;
+    if (input[PadDown].pressed) && (g.y < Q24_8::from_int(AV_SCREEN_HEIGHT as i32 - 1)) {
+        g.y += SPEED;
     }
     
-    if (input[PadLeft].pressed) && (g.x > 0) {
-        g.x = g.x - 48;
+    if (input[PadLeft].pressed) && (g.x > Q24_8::ZERO) {
+        g.x -= SPEED;
     }
     
-    if (input[PadRight].pressed) && ((g.x) < ((AV_SCREEN_WIDTH * 256)- 256)) {
-       g.x = g.x + 48;
+    if (input[PadRight].pressed) && (g.x < Q24_8::from_int(AV_SCREEN_WIDTH as i32 - 1)) {
+       g.x += SPEED;
     }
 
-    for i in range(0u, 255)
+    let x = g.x;
+    let y = g.y;
+    for obj in g.gobj.iter_mut().take(255)
     {
-        let x = g.x as i32;
-        let y = g.y as i32;
-        if g.gobj[i].x > x {g.gobj[i].dx = g.gobj[i].dx - 1;}
-        if g.gobj[i].x < x {g.gobj[i].dx = g.gobj[i].dx + 1;}
-        if g.gobj[i].y > y {g.gobj[i].dy = g.gobj[i].dy - 1;}
-        if g.gobj[i].y <y {g.gobj[i].dy = g.gobj[i].dy + 1;}
-        g.gobj[i].x = g.gobj[i].x + g.gobj[i].dx;
-        g.gobj[i].y = g.gobj[i].y + g.gobj[i].dy;
-        if g.gobj[i].dx > 256 {g.gobj[i].dx = 256;}
-        if g.gobj[i].dx < -255 {g.gobj[i].dx = -255;}
-        if g.gobj[i].dy > 256 {g.gobj[i].dy = 256;}
-        if g.gobj[i].dy < -255 {g.gobj[i].dy = -255;}
+        if obj.x > x {obj.dx -= Q24_8::EPSILON;}
+        if obj.x < x {obj.dx += Q24_8::EPSILON;}
+        if obj.y > y {obj.dy -= Q24_8::EPSILON;}
+        if obj.y < y {obj.dy += Q24_8::EPSILON;}
+        obj.x += obj.dx;
+        obj.y += obj.dy;
+        obj.dx = obj.dx.clamp(Q24_8::from_raw(-255), Q24_8::ONE);
+        obj.dy = obj.dy.clamp(Q24_8::from_raw(-255), Q24_8::ONE);
     }
     
 }
@@ -226,24 +354,20 @@ pub fn core_run()
 // for example using memcpy.
 pub fn snapshot_video()
 {
-    let g = &mut unsafe {mem_as_mut_slice::<GState>(transmute(&g_state), 1)}[0];
+    let g = unsafe { &*ptr::addr_of!(g_state) };
     unsafe
     {
         snapshotx = g.x;
         snapshoty = g.y;
         snapshotgobj_idx = g.gobj_idx;
-        for i in range(0, 255)
-        {
-            snapshotgobj[i] = g.gobj[i];
-        }
-        
+        snapshotgobj = g.gobj;
     }
 }
 
-static mut snapshotgobj: [GObj, ..256] = [GObj{x: 0, y: 0, dx: 0, dy: 0}, ..256];
+static mut snapshotgobj: [GObj; 256] = [GObj::ZERO; 256];
 static mut snapshotgobj_idx: u32 = 0;
-static mut snapshotx: u32 = 0;
-static mut snapshoty: u32 = 0;
+static mut snapshotx: Q24_8 = Q24_8::ZERO;
+static mut snapshoty: Q24_8 = Q24_8::ZERO;
 
 // This function renders one frame video in a separate thread. It may only access
 // the state saved in snapshot_video(). It must take into account
@@ -258,10 +382,11 @@ pub fn render_video()
 {
     image_loader();
     unsafe {
-            write_pixel(snapshotx/256, snapshoty/256);
-        for i in range(0, snapshotgobj_idx)
+            write_pixel(snapshotx.to_int() as u32, snapshoty.to_int() as u32);
+        let gobj = &*ptr::addr_of!(snapshotgobj);
+        for obj in &gobj[..snapshotgobj_idx as usize]
         {
-            blit_sprite((snapshotgobj[i as uint].x / 256), (snapshotgobj[i as uint].y / 256));
+            blit_sprite(obj.x.to_int(), obj.y.to_int());
         }
     }
 }
@@ -269,87 +394,121 @@ pub fn render_video()
 // This function returns the size in bytes of the serialized core logic state
 // produced by serialize_core_state(). It must not change at runtime, so be
 // careful with heap allocation.
-pub fn get_serialize_size() -> uint
+pub fn get_serialize_size() -> usize
 {
-    0
+    8 + 4 * 3 + 1 + 256 * 4 * 4
 }
 
 // This function saves all core logic state to a known format in a memory buffer.
 // It must be possible to restore state with unserialize_core_state() on any
 // platform, so serialize to a fixed endianness and take care with pointers.
+// The StateWriter methods write little endian values.
 // It may be simpler to avoid using pointers in the core state and use array
 // indices instead. Input state should not be serialized here as rust-libretro
 // serializes it automatically. Video state should not be serialized here as
 // it is generated from the core state in snapshot_video().
-pub fn serialize_core_state()
+// Save states are used by the frontend and by rewind, which may save state
+// after every few core logic updates, so keep this fast. Naming sections of
+// the state with state.section() lets the automatic determinism check core
+// option say where the state of a nondeterministic core_run() differs.
+pub fn serialize_core_state(state: &mut StateWriter)
 {
+    let g = unsafe { &*ptr::addr_of!(g_state) };
+
+    state.section("player");
+    state.write_u64(g.frame as u64);
+    g.x.serialize(state);
+    g.y.serialize(state);
+    state.write_u32(g.gobj_idx);
+    state.write_bool(g.old_a);
+    state.section("objects");
+    for obj in g.gobj.iter() {
+        obj.x.serialize(state);
+        obj.y.serialize(state);
+        obj.dx.serialize(state);
+        obj.dy.serialize(state);
+    }
 }
 
 // This function restores the core logic state serialized in
 // serialize_core_state().
-pub fn unserialize_core_state()
+pub fn unserialize_core_state(state: &mut StateReader)
 {
+    let g = unsafe { &mut *ptr::addr_of_mut!(g_state) };
+
+    g.frame = state.read_u64() as usize;
+    g.x = Q24_8::unserialize(state);
+    g.y = Q24_8::unserialize(state);
+    g.gobj_idx = state.read_u32();
+    g.old_a = state.read_bool();
+    for obj in g.gobj.iter_mut() {
+        obj.x = Q24_8::unserialize(state);
+        obj.y = Q24_8::unserialize(state);
+        obj.dx = Q24_8::unserialize(state);
+        obj.dy = Q24_8::unserialize(state);
+    }
 }
 
 struct GState
 {
-    frame: uint,
-    x: u32,
-    y: u32,
+    frame: usize,
+    x: Q24_8,
+    y: Q24_8,
     gobj_idx: u32,
     old_a: bool,
-    gobj: [GObj, ..256]
+    gobj: [GObj; 256]
 }
 
+#[derive(Clone, Copy)]
 struct GObj
 {
-    x: i32,
-    y: i32,
-    dx: i32,
-    dy: i32
+    x: Q24_8,
+    y: Q24_8,
+    dx: Q24_8,
+    dy: Q24_8
+}
+
+impl GObj
+{
+    const ZERO: GObj = GObj{x: Q24_8::ZERO, y: Q24_8::ZERO, dx: Q24_8::ZERO, dy: Q24_8::ZERO};
 }
 
 static mut g_state: GState =
 GState
 {
     frame: 0,
-    x: 0,
-    y: 0,
+    x: Q24_8::ZERO,
+    y: Q24_8::ZERO,
     gobj_idx: 0,
     old_a: false,
-    gobj:[GObj{x: 0, y: 0, dx: 0, dy: 0}, ..256]
+    gobj:[GObj::ZERO; 256]
 };
 
-unsafe fn mem_as_mut_slice<T>(base: *mut T, length: uint) -> &'static mut [T] 
+unsafe fn mem_as_mut_slice<T>(base: *mut T, length: usize) -> &'static mut [T]
 {
-      transmute(core::raw::Slice {data: base as *const T, len: length})
-}
-
-unsafe fn mem_as_slice<T>(base: *const T, length: uint) -> &'static [T] 
-{
-      transmute(core::raw::Slice {data: base as *const T, len: length})
+      slice::from_raw_parts_mut(base, length)
 }
 
 
-pub static RAWIMAGE: &'static [u8] = include_bin!("rgb565.raw");
+pub static RAWIMAGE: &[u8] = include_bytes!("rgb565.raw");
 
 fn image_loader()
 {
    unsafe {
-       core::intrinsics::copy_nonoverlapping_memory::<u8>(transmute(frame_buf),
-                                                    transmute(&RAWIMAGE[0]),
-                                                    (AV_SCREEN_WIDTH *
-                                                     AV_SCREEN_HEIGHT * 2) as uint);
+       ptr::copy_nonoverlapping(RAWIMAGE.as_ptr(),
+                                frame_buf as *mut u8,
+                                (AV_SCREEN_WIDTH *
+                                 AV_SCREEN_HEIGHT * 2) as usize);
    }
 }
 
 fn write_pixel(x: u32, y: u32)
 {
-   let buf_slice = unsafe {mem_as_mut_slice(frame_buf as *mut u16, AV_SCREEN_WIDTH as uint * AV_SCREEN_HEIGHT as uint)};
-   buf_slice[x as uint + y as uint * AV_SCREEN_WIDTH as uint] = 0xffff;   
+   let buf_slice = unsafe {mem_as_mut_slice(frame_buf as *mut u16, AV_SCREEN_WIDTH as usize * AV_SCREEN_HEIGHT as usize)};
+   buf_slice[x as usize + y as usize * AV_SCREEN_WIDTH as usize] = 0xffff;
 }
 
-pub static RAWSPRITE: &'static [u8] = include_bin!("sprite.raw");
+pub static RAWSPRITE: &[u8] = include_bytes!("sprite.raw");
 
 unsafe fn blit_sprite(mut x: i32, mut y: i32)
 {
@@ -371,14 +530,16 @@ unsafe fn blit_sprite(mut x: i32, mut y: i32)
     if y + h >= AV_SCREEN_HEIGHT { h = AV_SCREEN_HEIGHT - y; }
 
     
-    let buf_slice = mem_as_mut_slice(frame_buf as *mut u16, AV_SCREEN_WIDTH as uint * AV_SCREEN_HEIGHT as uint);
-    let spr_slice = mem_as_slice(RAWSPRITE.as_ptr() as *const u16, 96 as uint * 19 as uint);
-    
-    for iy in range(starty, h) {
-        for ix in range (startx, w) {
-            let spr_pix =  spr_slice.unsafe_get(ix as uint + iy as uint * 96);
-            if *spr_pix != 0 {
-                *buf_slice.unsafe_mut(x as uint - startx as uint + ix as uint + (y as uint - starty as uint + iy as uint) * AV_SCREEN_WIDTH as uint) = *spr_pix;
+    let buf_slice = mem_as_mut_slice(frame_buf as *mut u16, AV_SCREEN_WIDTH as usize * AV_SCREEN_HEIGHT as usize);
+
+    for iy in starty..h {
+        for ix in startx..w {
+            // sprite data is not guaranteed to be aligned for u16
+            let spr_idx = (ix as usize + iy as usize * 96) * 2;
+            let spr_pix = u16::from_ne_bytes([*RAWSPRITE.get_unchecked(spr_idx),
+                                              *RAWSPRITE.get_unchecked(spr_idx + 1)]);
+            if spr_pix != 0 {
+                *buf_slice.get_unchecked_mut(x as usize - startx as usize + ix as usize + (y as usize - starty as usize + iy as usize) * AV_SCREEN_WIDTH as usize) = spr_pix;
             }
         }
     }
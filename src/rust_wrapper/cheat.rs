@@ -0,0 +1,366 @@
+//! Cheat codes set by the frontend.
+//!
+//! Frontends pass cheats to retro_cheat_set() as text. Each cheat is first
+//! offered to the core's core_cheat() hook, for codes only that game
+//! understands. Otherwise it is read as one or more memory codes, separated
+//! by spaces, '+' or ';':
+//!
+//! * ADDR=VALUE or ADDR:VALUE writes VALUE to ADDR.
+//! * ADDR?COMPARE=VALUE writes VALUE only while ADDR holds COMPARE.
+//! * ADDR==VALUE, ADDR!=VALUE, ADDR<VALUE or ADDR>VALUE apply the rest of the
+//!   cheat only while the comparison holds.
+//!
+//! Numbers are hexadecimal, optionally starting with 0x or $. The number of
+//! digits in VALUE sets how many bytes are read or written: up to 2 digits
+//! for 1 byte, 4 for 2 bytes and 8 for 4 bytes. Addresses are those of the
+//! memory map set up with map_memory(), or offsets into system RAM for cores
+//! without one.
+//!
+//! Cheats are applied after every logic update while the cheats core option
+//! is on, so the state saved for rewind and save states includes them.
+
+use core::ffi::c_uint;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::{get_variable, memory, notify};
+
+const CHEATS_KEY: &str = "cheats\0";
+
+/// A game-specific cheat returned by core_cheat(), run after every logic
+/// update while the cheat is enabled.
+pub type CoreCheat = Box<dyn FnMut() + Send>;
+
+#[derive(Clone, Copy, Debug)]
+enum Condition
+{
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Kind
+{
+    Write,
+    /// Writes only while memory holds this value.
+    Compare(u32),
+    /// Applies the rest of the cheat only while the comparison holds.
+    If(Condition),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Code
+{
+    address: usize,
+    bytes: usize,
+    value: u32,
+    kind: Kind,
+}
+
+enum Action
+{
+    Codes(Vec<Code>),
+    Core(CoreCheat),
+}
+
+struct Cheat
+{
+    index: c_uint,
+    action: Action,
+}
+
+static CHEATS: Mutex<Vec<Cheat>> = Mutex::new(Vec::new());
+static ENABLED: AtomicBool = AtomicBool::new(true);
+/// Set while apply() has the cheats out of CHEATS.
+static APPLYING: AtomicBool = AtomicBool::new(false);
+
+fn lock() -> MutexGuard<'static, Vec<Cheat>>
+{
+    CHEATS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Builds the cheats option as null terminated key and value strings for
+/// RETRO_ENVIRONMENT_SET_VARIABLES.
+pub fn variable() -> (String, String)
+{
+    (CHEATS_KEY.to_string(), "Cheats; on|off\0".to_string())
+}
+
+/// Reads the cheats option. Called whenever the frontend reports a core
+/// option change.
+pub fn load_options()
+{
+    ENABLED.store(get_variable(CHEATS_KEY).as_deref() != Some("off"), Ordering::Relaxed);
+}
+
+/// True while any cheat is being applied, so cores can, for example, keep
+/// cheated scores off a high score table.
+pub fn cheats_active() -> bool
+{
+    ENABLED.load(Ordering::Relaxed) && (APPLYING.load(Ordering::Relaxed) || !lock().is_empty())
+}
+
+/// retro_cheat_reset(): forgets every cheat.
+pub fn reset()
+{
+    let cheats = std::mem::take(&mut *lock());
+    // Dropped outside the lock, in case a core cheat's drop looks at cheats
+    drop(cheats);
+}
+
+/// retro_cheat_set(): replaces the cheat at index, removing it if disabled.
+/// Cheats that can't be understood are logged and ignored.
+pub fn set(index: c_uint, enabled: bool, code: &str)
+{
+    let previous = {
+        let mut cheats = lock();
+        cheats.iter().position(|cheat| cheat.index == index).map(|i| cheats.remove(i))
+    };
+    drop(previous);
+    if !enabled {
+        return;
+    }
+
+    let action = match super::super::core_cheat(code) {
+        Some(core_cheat) => Action::Core(core_cheat),
+        None => match parse(code) {
+            Ok(codes) => Action::Codes(codes),
+            Err(reason) => {
+                log_warn!("Cheat {} ignored: {}", index, reason);
+                notify("Cheat code not understood, see the log", 3.0);
+                return;
+            }
+        },
+    };
+    log_debug!("Cheat {} set: {}", index, code);
+    lock().push(Cheat { index, action });
+}
+
+/// Applies the cheats, after every logic update.
+pub fn apply()
+{
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    // Core cheats run outside the lock, so they can call cheats_active()
+    let mut cheats = std::mem::take(&mut *lock());
+    APPLYING.store(!cheats.is_empty(), Ordering::Relaxed);
+    for cheat in &mut cheats {
+        match &mut cheat.action {
+            Action::Codes(codes) => apply_codes(codes),
+            Action::Core(core_cheat) => core_cheat(),
+        }
+    }
+    let mut shared = lock();
+    cheats.append(&mut shared);
+    *shared = cheats;
+    APPLYING.store(false, Ordering::Relaxed);
+}
+
+fn apply_codes(codes: &[Code])
+{
+    for code in codes {
+        let Some(current) = read(code.address, code.bytes) else {
+            return;
+        };
+        match code.kind {
+            Kind::Write => write(code.address, code.bytes, code.value),
+            Kind::Compare(compare) => {
+                if current == compare {
+                    write(code.address, code.bytes, code.value);
+                }
+            }
+            Kind::If(condition) => {
+                let holds = match condition {
+                    Condition::Equal => current == code.value,
+                    Condition::NotEqual => current != code.value,
+                    Condition::Less => current < code.value,
+                    Condition::Greater => current > code.value,
+                };
+                if !holds {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Finds each byte of a value in memory, most significant first.
+fn locate(address: usize, bytes: usize) -> Option<Vec<*mut u8>>
+{
+    let (_, big_endian) = memory::resolve(address)?;
+    let mut located = (0..bytes)
+        .map(|i| memory::resolve(address.wrapping_add(i)).map(|(byte, _)| byte))
+        .collect::<Option<Vec<_>>>()?;
+    if !big_endian {
+        located.reverse();
+    }
+    Some(located)
+}
+
+fn read(address: usize, bytes: usize) -> Option<u32>
+{
+    let located = locate(address, bytes)?;
+    Some(located.iter().fold(0, |value, &byte| value << 8 | unsafe { *byte } as u32))
+}
+
+fn write(address: usize, bytes: usize, value: u32)
+{
+    if let Some(located) = locate(address, bytes) {
+        for (i, &byte) in located.iter().rev().enumerate() {
+            unsafe { *byte = (value >> (8 * i)) as u8; }
+        }
+    }
+}
+
+fn parse(cheat: &str) -> Result<Vec<Code>, String>
+{
+    let codes = cheat.split(|c: char| c == '+' || c == ';' || c.is_whitespace())
+        .filter(|code| !code.is_empty())
+        .map(parse_code)
+        .collect::<Result<Vec<_>, _>>()?;
+    if codes.is_empty() {
+        return Err("no codes".to_string());
+    }
+    for code in &codes {
+        if read(code.address, code.bytes).is_none() {
+            return Err(format!("address {:#x} isn't mapped to memory", code.address));
+        }
+    }
+    Ok(codes)
+}
+
+fn parse_code(code: &str) -> Result<Code, String>
+{
+    const CONDITIONS: [(&str, Condition); 4] = [("==", Condition::Equal),
+                                                ("!=", Condition::NotEqual),
+                                                ("<", Condition::Less),
+                                                (">", Condition::Greater)];
+
+    let (address, value, kind) =
+        // Split at the first operator in the code, not the first one listed
+        if let Some((at, op, condition)) = CONDITIONS.iter()
+            .filter_map(|&(op, condition)| code.find(op).map(|at| (at, op, condition)))
+            .min_by_key(|&(at, ..)| at) {
+            (&code[..at], &code[at + op.len()..], Kind::If(condition))
+        } else if let Some((address, rest)) = code.split_once('?') {
+            let (compare, value) = rest.split_once('=')
+                .ok_or_else(|| format!("\"{}\" has no value after the comparison", code))?;
+            (address, value, Kind::Compare(parse_hex(compare, code)?.1))
+        } else if let Some((address, value)) = code.split_once(['=', ':']) {
+            (address, value, Kind::Write)
+        } else {
+            return Err(format!("\"{}\" isn't an address and value", code));
+        };
+
+    let (_, address) = parse_hex(address, code)?;
+    let (digits, value) = parse_hex(value, code)?;
+    let bytes = match digits {
+        1..=2 => 1,
+        3..=4 => 2,
+        _ => 4,
+    };
+    if let Kind::Compare(compare) = kind {
+        if bytes < 4 && compare >> (8 * bytes) != 0 {
+            return Err(format!("\"{}\" compares with a value wider than it writes", code));
+        }
+    }
+    Ok(Code { address: address as usize, bytes, value, kind })
+}
+
+/// Reads a hexadecimal number of up to 8 digits, returning the number of
+/// digits too.
+fn parse_hex(text: &str, code: &str) -> Result<(usize, u32), String>
+{
+    let digits = text.strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .or_else(|| text.strip_prefix('$'))
+        .unwrap_or(text);
+    match u32::from_str_radix(digits, 16) {
+        Ok(value) if !digits.starts_with('+') && digits.len() <= 8 => Ok((digits.len(), value)),
+        _ => Err(format!("\"{}\" in \"{}\" isn't a hexadecimal number of up to 8 digits", text, code)),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::memory::{register_memory, MemoryId};
+
+    #[test]
+    fn byte_and_word_writes()
+    {
+        let code = parse_code("1A=ff").unwrap();
+        assert_eq!((code.address, code.bytes, code.value), (0x1a, 1, 0xff));
+        assert!(matches!(code.kind, Kind::Write));
+        let code = parse_code("$0010:0102").unwrap();
+        assert_eq!((code.address, code.bytes, code.value), (0x10, 2, 0x102));
+        let code = parse_code("0x7=5").unwrap();
+        assert_eq!((code.address, code.bytes, code.value), (7, 1, 5));
+        let code = parse_code("0=00012345").unwrap();
+        assert_eq!((code.bytes, code.value), (4, 0x12345));
+    }
+
+    #[test]
+    fn compare_then_write()
+    {
+        let code = parse_code("20?03=09").unwrap();
+        assert_eq!((code.address, code.bytes, code.value), (0x20, 1, 9));
+        assert!(matches!(code.kind, Kind::Compare(3)));
+        assert!(parse_code("20?100=09").is_err());
+        assert!(parse_code("20?03").is_err());
+    }
+
+    #[test]
+    fn conditions()
+    {
+        for (code, expected) in [("8==1", Condition::Equal), ("8!=1", Condition::NotEqual),
+                                 ("8<1", Condition::Less), ("8>1", Condition::Greater)] {
+            let kind = parse_code(code).unwrap().kind;
+            assert!(matches!(kind, Kind::If(condition) if condition as u8 == expected as u8),
+                    "{} gave {:?}", code, kind);
+        }
+    }
+
+    #[test]
+    fn splits_at_the_first_condition()
+    {
+        for (code, value) in [("5>0<1", "0<1"), ("5<0>1", "0>1"), ("5!=0==1", "0==1"),
+                              ("5==0<1", "0<1")] {
+            let error = parse_code(code).unwrap_err();
+            assert!(error.starts_with(&format!("\"{}\"", value)), "{}: {}", code, error);
+        }
+    }
+
+    #[test]
+    fn malformed_codes()
+    {
+        for code in ["", "12", "=5", "5=", "zz=1", "1=zz", "1=+5", "1=123456789", "123456789=1",
+                     "1?=2", "1?2", "-1=2"] {
+            assert!(parse_code(code).is_err(), "{:?} accepted", code);
+        }
+    }
+
+    #[test]
+    fn joined_codes_apply_to_memory()
+    {
+        let ram: &'static mut [u8] = Box::leak(vec![0u8; 16].into_boxed_slice());
+        let data = ram.as_ptr();
+        register_memory(MemoryId::SystemRam, ram);
+        let ram = || unsafe { std::slice::from_raw_parts(data, 16) };
+
+        let codes = parse("0=12+2:0304;4?00=ff 6==00+7=77").unwrap();
+        assert_eq!(codes.len(), 5);
+        apply_codes(&codes);
+        // System RAM without a memory map is little endian
+        assert_eq!(&ram()[..8], &[0x12, 0, 0x04, 0x03, 0xff, 0, 0, 0x77]);
+
+        assert!(parse("").is_err());
+        assert!(parse("0=1+zz=1").is_err());
+        assert!(parse("10=1").unwrap_err().contains("isn't mapped"));
+        assert!(parse("f=0102").is_err());
+    }
+}
@@ -0,0 +1,197 @@
+//! The determinism check, a debugging aid for core developers.
+//!
+//! With the determinism check core option on, every video frame saves the
+//! state before its logic updates and logs the input core_run() polls and a
+//! hash of the state after every update. Once the frame's updates have run,
+//! it restores the saved state, runs the updates again with the logged input,
+//! and compares the hashes. The first logic update whose state differs is
+//! reported, with the section of the state named by StateWriter::section()
+//! and the byte that differs, and the check turns itself off until the option
+//! is changed.
+//!
+//! This finds state that depends on anything but the input, such as time,
+//! threads, uninitialised memory or state missing from the save state. It
+//! can't find results that differ between platforms. Frames take roughly
+//! twice as long to run while checking.
+
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use super::{cheat, get_variable, input, notify, serialize};
+
+const DETERMINISM_CHECK_KEY: &str = "determinism_check\0";
+
+struct Check
+{
+    enabled: bool,
+    replaying: bool,
+    /// State before the frame's first logic update.
+    start: Vec<u8>,
+    /// Input polled in each logic update, by player in the order polled.
+    inputs: Vec<Vec<(u32, u16)>>,
+    /// State after each logic update and its hash.
+    states: Vec<(u64, Vec<u8>)>,
+    /// Logic update of the frame being replayed, and its next input.
+    replay_tick: usize,
+    next_input: usize,
+    /// How the replayed logic update polled input differently, if it did.
+    input_mismatch: Option<String>,
+}
+
+static CHECK: Mutex<Check> = Mutex::new(Check {
+    enabled: false,
+    replaying: false,
+    start: Vec::new(),
+    inputs: Vec::new(),
+    states: Vec::new(),
+    replay_tick: 0,
+    next_input: 0,
+    input_mismatch: None,
+});
+
+fn lock() -> MutexGuard<'static, Check>
+{
+    CHECK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Builds the determinism check option as null terminated key and value
+/// strings for RETRO_ENVIRONMENT_SET_VARIABLES.
+pub fn variable() -> (String, String)
+{
+    (DETERMINISM_CHECK_KEY.to_string(), "Determinism check; off|on\0".to_string())
+}
+
+/// Reads the determinism check option. Called whenever the frontend reports
+/// a core option change.
+pub fn load_options()
+{
+    let enabled = get_variable(DETERMINISM_CHECK_KEY).as_deref() == Some("on");
+    let mut check = lock();
+    if enabled && !check.enabled {
+        log_info!("Determinism check on");
+    }
+    check.enabled = enabled;
+}
+
+/// Saves the state before a frame's logic updates, if checking.
+pub fn begin_frame()
+{
+    let mut check = lock();
+    if check.enabled {
+        check.start = serialize::save_vec();
+        check.inputs.clear();
+        check.states.clear();
+    }
+}
+
+/// Returns the logged input for a player while replaying, or None to read
+/// the input as usual.
+pub fn replay_input(player: u32) -> Option<u16>
+{
+    let mut check = lock();
+    if !check.replaying {
+        return None;
+    }
+    let index = check.next_input;
+    check.next_input += 1;
+    if check.input_mismatch.is_some() {
+        return Some(0);
+    }
+    match check.inputs.get(check.replay_tick).and_then(|inputs| inputs.get(index)) {
+        Some(&(logged_player, buttons)) if logged_player == player => Some(buttons),
+        _ => {
+            check.input_mismatch = Some(format!("logic update {} polled player {} at poll {}, \
+                                                 unlike the first run",
+                                                input::tick_time().tick, player, index));
+            Some(0)
+        }
+    }
+}
+
+/// Logs the input polled for a player in the current logic update.
+pub fn record_input(player: u32, buttons: u16)
+{
+    let mut check = lock();
+    if check.enabled && !check.replaying {
+        let tick = check.states.len();
+        if check.inputs.len() <= tick {
+            check.inputs.resize(tick + 1, Vec::new());
+        }
+        check.inputs[tick].push((player, buttons));
+    }
+}
+
+/// Hashes the state after a logic update, if checking.
+pub fn after_tick()
+{
+    let mut check = lock();
+    if check.enabled {
+        let state = serialize::save_vec();
+        check.states.push((hash(&state), state));
+    }
+}
+
+/// Runs the frame's logic updates again from the saved state and compares
+/// the state after each one with the first run.
+pub fn end_frame()
+{
+    let (start, ticks) = {
+        let mut check = lock();
+        if !check.enabled || check.start.is_empty() {
+            return;
+        }
+        (std::mem::take(&mut check.start), check.states.len())
+    };
+    let end = serialize::save_vec();
+
+    serialize::load(&start);
+    for tick in 0..ticks {
+        {
+            let mut check = lock();
+            check.replaying = true;
+            check.replay_tick = tick;
+            check.next_input = 0;
+        }
+        super::super::core_run();
+        cheat::apply();
+        input::end_tick();
+
+        let state = serialize::save_vec();
+        let mut check = lock();
+        check.replaying = false;
+        if let Some(mismatch) = check.input_mismatch.take() {
+            drop(check);
+            fail(&mismatch);
+            break;
+        }
+        let (expected_hash, expected) = &check.states[tick];
+        if hash(&state) != *expected_hash {
+            let offset = expected.iter().zip(&state).position(|(a, b)| a != b).unwrap_or(0);
+            drop(check);
+            let (_, sections) = serialize::save_sections();
+            let &(section_start, section) = sections.iter()
+                .rev()
+                .find(|&&(start, _)| start <= offset)
+                .unwrap();
+            fail(&format!("logic update {} differs in {} at byte {}",
+                          input::tick_time().tick - 1, section, offset - section_start));
+            break;
+        }
+    }
+    serialize::load(&end);
+}
+
+/// Reports a failed check and stops checking.
+fn fail(reason: &str)
+{
+    lock().enabled = false;
+    log_error!("Determinism check failed: {}", reason);
+    notify("Determinism check failed, see the log", 5.0);
+}
+
+/// FNV-1a, good enough to tell states apart.
+pub fn hash(data: &[u8]) -> u64
+{
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -0,0 +1,394 @@
+//! Multi-disc games.
+//!
+//! The frontend swaps disc images through the libretro disk control
+//! interface, which calls a DiskControl set with set_disk_control() from
+//! core_load_game(). Cores that only need to know which image to read can
+//! use DiskList, which keeps a list of image paths; loading an M3U playlist
+//! sets one up automatically with the playlist's images. Either way, the core
+//! finds the image in the tray with inserted_disk().
+
+use core::ffi::c_uint;
+use std::ffi::CStr;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use super::environment;
+use super::libretro::*;
+use super::panic::catch_panic;
+
+/// What a core implements to let the frontend swap its disc images. Image
+/// indexes start at 0. While the tray is open the frontend may change the
+/// image index and replace images, and an index of num_images() or more
+/// means no image is inserted.
+pub trait DiskControl: Send
+{
+    /// Opens or closes the tray, returning false if it can't.
+    fn set_eject_state(&mut self, ejected: bool) -> bool;
+    fn eject_state(&self) -> bool;
+    fn image_index(&self) -> u32;
+    fn set_image_index(&mut self, index: u32) -> bool;
+    fn num_images(&self) -> u32;
+    /// Replaces the image at index with the one at path, or removes it from
+    /// the list if path is None, moving the later images down.
+    fn replace_image(&mut self, index: u32, path: Option<&str>) -> bool;
+    /// Adds an empty slot to the end of the list, for replace_image() to fill.
+    fn add_image(&mut self) -> bool;
+
+    /// Path of the image at index, if known, for inserted_disk().
+    fn image_path(&self, _index: u32) -> Option<String>
+    {
+        None
+    }
+}
+
+/// A DiskControl keeping a list of image paths, starting with the first
+/// image inserted.
+#[derive(Clone, Debug, Default)]
+pub struct DiskList
+{
+    images: Vec<Option<String>>,
+    index: u32,
+    ejected: bool,
+}
+
+impl DiskList
+{
+    pub fn new(images: Vec<String>) -> DiskList
+    {
+        DiskList { images: images.into_iter().map(Some).collect(), index: 0, ejected: false }
+    }
+
+    /// Reads an M3U playlist, one image path per line. Relative paths are
+    /// relative to the playlist, and lines starting with # are comments.
+    pub fn from_m3u(path: &str) -> Result<DiskList, String>
+    {
+        let text = fs::read_to_string(path).map_err(|error| format!("can't read {}: {}", path, error))?;
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+        let images: Vec<String> = text.trim_start_matches('\u{feff}')
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| dir.join(line).to_string_lossy().into_owned())
+            .collect();
+        if images.is_empty() {
+            return Err(format!("{} lists no images", path));
+        }
+        Ok(DiskList::new(images))
+    }
+}
+
+impl DiskControl for DiskList
+{
+    fn set_eject_state(&mut self, ejected: bool) -> bool
+    {
+        self.ejected = ejected;
+        true
+    }
+
+    fn eject_state(&self) -> bool
+    {
+        self.ejected
+    }
+
+    fn image_index(&self) -> u32
+    {
+        self.index
+    }
+
+    fn set_image_index(&mut self, index: u32) -> bool
+    {
+        if !self.ejected {
+            return false;
+        }
+        self.index = index;
+        true
+    }
+
+    fn num_images(&self) -> u32
+    {
+        self.images.len() as u32
+    }
+
+    fn replace_image(&mut self, index: u32, path: Option<&str>) -> bool
+    {
+        if !self.ejected || index as usize >= self.images.len() {
+            return false;
+        }
+        match path {
+            Some(path) => self.images[index as usize] = Some(path.to_string()),
+            None => {
+                self.images.remove(index as usize);
+                if index < self.index {
+                    self.index -= 1;
+                } else if index == self.index {
+                    self.index = self.images.len() as u32;
+                }
+            }
+        }
+        true
+    }
+
+    fn add_image(&mut self) -> bool
+    {
+        self.images.push(None);
+        true
+    }
+
+    fn image_path(&self, index: u32) -> Option<String>
+    {
+        self.images.get(index as usize).cloned().flatten()
+    }
+}
+
+static DISK: Mutex<Option<Box<dyn DiskControl>>> = Mutex::new(None);
+/// Path of the image in the tray, updated after every call into the
+/// DiskControl, so inserted_disk() can be used from within one.
+static INSERTED: Mutex<Option<String>> = Mutex::new(None);
+
+fn lock() -> MutexGuard<'static, Option<Box<dyn DiskControl>>>
+{
+    DISK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn set_inserted(inserted: Option<String>)
+{
+    *INSERTED.lock().unwrap_or_else(PoisonError::into_inner) = inserted;
+}
+
+fn inserted_path(control: &dyn DiskControl) -> Option<String>
+{
+    if control.eject_state() {
+        return None;
+    }
+    control.image_path(control.image_index())
+}
+
+/// Lets the frontend swap disc images through control until the content is
+/// unloaded, replacing any DiskList set up for an M3U playlist. Call from
+/// core_load_game().
+pub fn set_disk_control(control: impl DiskControl + 'static)
+{
+    let inserted = inserted_path(&control);
+    *lock() = Some(Box::new(control));
+    set_inserted(inserted);
+}
+
+/// Path of the image in the tray, or None if the tray is open, it's empty or
+/// the DiskControl doesn't know image paths.
+pub fn inserted_disk() -> Option<String>
+{
+    INSERTED.lock().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// Sets up a DiskList when the content is an M3U playlist, before
+/// core_load_game(). A playlist that can't be read is logged and ignored.
+pub fn load(path: Option<&str>)
+{
+    clear();
+    let Some(path) = path.filter(|path| path.to_ascii_lowercase().ends_with(".m3u")) else {
+        return;
+    };
+    match DiskList::from_m3u(path) {
+        Ok(list) => {
+            log_info!("Playlist of {} disc images", list.num_images());
+            set_disk_control(list);
+        }
+        Err(reason) => log_error!("Playlist not loaded: {}", reason),
+    }
+}
+
+/// Passes the disk control interface to the frontend if the core, or an M3U
+/// playlist, set up a DiskControl.
+pub fn register()
+{
+    static CALLBACK: retro_disk_control_callback = retro_disk_control_callback {
+        set_eject_state,
+        get_eject_state,
+        get_image_index,
+        set_image_index,
+        get_num_images,
+        replace_image_index,
+        add_image_index,
+    };
+
+    if lock().is_some() &&
+       !environment(RETRO_ENVIRONMENT_SET_DISK_CONTROL_INTERFACE,
+                    &CALLBACK as *const _ as *mut _) {
+        log_warn!("Frontend can't swap disc images");
+    }
+}
+
+/// Forgets the DiskControl, when the content is unloaded.
+pub fn clear()
+{
+    let control = lock().take();
+    set_inserted(None);
+    drop(control);
+}
+
+/// Runs f on the DiskControl from a frontend callback, or returns fallback
+/// if there isn't one. The DiskControl is taken out of DISK while f runs, so
+/// it can call inserted_disk() or set_disk_control() without deadlocking.
+fn with_control<T: Copy>(name: &str, fallback: T, f: impl FnOnce(&mut dyn DiskControl) -> T) -> T
+{
+    catch_panic(name, fallback, || {
+        let Some(mut control) = lock().take() else {
+            return fallback;
+        };
+        let result = f(&mut *control);
+        let inserted = inserted_path(&*control);
+        let mut disk = lock();
+        // Unless f replaced it
+        if disk.is_none() {
+            *disk = Some(control);
+            drop(disk);
+            set_inserted(inserted);
+        }
+        result
+    })
+}
+
+extern "C" fn set_eject_state(ejected: u8) -> u8
+{
+    with_control("set_eject_state", false, |control| control.set_eject_state(ejected != 0)) as u8
+}
+
+extern "C" fn get_eject_state() -> u8
+{
+    with_control("get_eject_state", false, |control| control.eject_state()) as u8
+}
+
+extern "C" fn get_image_index() -> c_uint
+{
+    with_control("get_image_index", 0, |control| control.image_index())
+}
+
+extern "C" fn set_image_index(index: c_uint) -> u8
+{
+    with_control("set_image_index", false, |control| control.set_image_index(index)) as u8
+}
+
+extern "C" fn get_num_images() -> c_uint
+{
+    with_control("get_num_images", 0, |control| control.num_images())
+}
+
+extern "C" fn replace_image_index(index: c_uint, info: *const retro_game_info) -> u8
+{
+    with_control("replace_image_index", false, |control| {
+        if info.is_null() {
+            return control.replace_image(index, None);
+        }
+        let path = unsafe { (*info).path };
+        if path.is_null() {
+            log_warn!("Disc images need a path");
+            return false;
+        }
+        control.replace_image(index, Some(&unsafe { CStr::from_ptr(path) }.to_string_lossy()))
+    }) as u8
+}
+
+extern "C" fn add_image_index() -> u8
+{
+    with_control("add_image_index", false, |control| control.add_image()) as u8
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Writes a playlist to its own directory under the temporary directory.
+    fn playlist(name: &str, text: &str) -> PathBuf
+    {
+        let dir = std::env::temp_dir().join(format!("disk-test-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("game.m3u");
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn list(images: &[&str], index: u32) -> DiskList
+    {
+        let mut list = DiskList::new(images.iter().map(|image| image.to_string()).collect());
+        list.index = index;
+        list.ejected = true;
+        list
+    }
+
+    #[test]
+    fn reads_playlists()
+    {
+        let path = playlist("read", "\u{feff}# Discs\r\ndisc1.cue\n\n  disc2.cue  \n\
+                                     #disc3.cue\nsub/disc3.cue\n/abs/disc4.cue\n");
+        let dir = path.parent().unwrap();
+        let list = DiskList::from_m3u(path.to_str().unwrap()).unwrap();
+        let images: Vec<_> = (0..list.num_images()).map(|i| list.image_path(i).unwrap()).collect();
+        assert_eq!(images, [dir.join("disc1.cue").to_string_lossy(),
+                            dir.join("disc2.cue").to_string_lossy(),
+                            dir.join("sub/disc3.cue").to_string_lossy(),
+                            "/abs/disc4.cue".into()]);
+        assert_eq!((list.image_index(), list.eject_state()), (0, false));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn empty_playlists_are_refused()
+    {
+        let path = playlist("empty", "\u{feff}# Nothing here\n\n   \n");
+        let error = DiskList::from_m3u(path.to_str().unwrap()).unwrap_err();
+        assert!(error.contains("lists no images"), "{}", error);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert!(DiskList::from_m3u(path.to_str().unwrap()).unwrap_err().contains("can't read"));
+    }
+
+    #[test]
+    fn removing_images_moves_the_index()
+    {
+        // Before the current image
+        let mut before = list(&["a", "b", "c"], 2);
+        assert!(before.replace_image(0, None));
+        assert_eq!((before.num_images(), before.image_index()), (2, 1));
+        assert_eq!(before.image_path(1).as_deref(), Some("c"));
+
+        // The current image, leaving the tray empty
+        let mut current = list(&["a", "b", "c"], 1);
+        assert!(current.replace_image(1, None));
+        assert_eq!((current.num_images(), current.image_index()), (2, 2));
+        assert_eq!(current.image_path(current.image_index()), None);
+
+        // After the current image
+        let mut after = list(&["a", "b", "c"], 0);
+        assert!(after.replace_image(2, None));
+        assert_eq!((after.num_images(), after.image_index()), (2, 0));
+
+        assert!(!after.replace_image(2, None));
+    }
+
+    #[test]
+    fn replacing_and_adding_images()
+    {
+        let mut list = list(&["a"], 0);
+        assert!(list.add_image());
+        assert_eq!(list.image_path(1), None);
+        assert!(list.replace_image(1, Some("b")));
+        assert_eq!(list.image_path(1).as_deref(), Some("b"));
+        assert!(list.set_image_index(1));
+        assert_eq!(list.image_index(), 1);
+    }
+
+    #[test]
+    fn closed_tray_refuses_changes()
+    {
+        let mut list = list(&["a", "b"], 0);
+        assert!(list.set_eject_state(false));
+        assert!(!list.set_image_index(1));
+        assert!(!list.replace_image(0, Some("c")));
+        assert!(!list.replace_image(0, None));
+        assert_eq!(list.image_index(), 0);
+        assert_eq!(list.image_path(0).as_deref(), Some("a"));
+        assert_eq!(list.num_images(), 2);
+    }
+}
@@ -0,0 +1,876 @@
+//! Fixed point math for core logic.
+//!
+//! Floating point results can differ between platforms and compilers, which
+//! breaks save states, input recordings and netplay. Fixed<FRAC> is a signed
+//! 32 bit integer counting 1/2^FRAC units, so all its arithmetic is integer
+//! arithmetic with the same results everywhere. Q24_8 and Q16_16 are the
+//! usual choices, Q24_8 for positions on large playfields and Q16_16 where
+//! more precision is needed.
+//!
+//! The operators wrap on overflow, in debug and release builds alike, so a
+//! debug build can't panic where a release build carries on. Saturating
+//! versions are provided where clamping is wanted. Trigonometry uses binary
+//! angles and lookup tables computed at compile time, and square roots are
+//! exact integer square roots, rounded down.
+
+use core::fmt;
+use core::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use super::serialize::{StateReader, StateWriter};
+
+/// A fixed point number with FRAC fractional bits, at most 30.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Fixed<const FRAC: u32>(i32);
+
+/// 24 integer bits and 8 fractional bits, steps of 1/256.
+#[allow(non_camel_case_types)]
+pub type Q24_8 = Fixed<8>;
+/// 16 integer bits and 16 fractional bits, steps of 1/65536.
+#[allow(non_camel_case_types)]
+pub type Q16_16 = Fixed<16>;
+
+impl<const FRAC: u32> Fixed<FRAC>
+{
+    pub const ZERO: Self = Fixed(0);
+    pub const ONE: Self = Fixed(1 << FRAC);
+    /// The smallest step, 1/2^FRAC.
+    pub const EPSILON: Self = Fixed(1);
+    pub const MIN: Self = Fixed(i32::MIN);
+    pub const MAX: Self = Fixed(i32::MAX);
+
+    pub const fn from_int(value: i32) -> Self
+    {
+        Fixed(value.wrapping_shl(FRAC))
+    }
+
+    /// Makes a number from its raw value in 1/2^FRAC units.
+    pub const fn from_raw(raw: i32) -> Self
+    {
+        Fixed(raw)
+    }
+
+    /// numerator / denominator, rounded towards zero.
+    pub const fn from_ratio(numerator: i32, denominator: i32) -> Self
+    {
+        Fixed((((numerator as i64) << FRAC) / denominator as i64) as i32)
+    }
+
+    pub const fn raw(self) -> i32
+    {
+        self.0
+    }
+
+    /// The integer part, rounded down.
+    pub const fn to_int(self) -> i32
+    {
+        self.0 >> FRAC
+    }
+
+    /// The nearest integer, halves rounded up.
+    pub const fn round(self) -> i32
+    {
+        ((self.0 as i64 + (1 << FRAC >> 1)) >> FRAC) as i32
+    }
+
+    pub const fn floor(self) -> Self
+    {
+        Fixed(self.0 & !((1 << FRAC) - 1))
+    }
+
+    pub const fn ceil(self) -> Self
+    {
+        Fixed(self.0.wrapping_add((1 << FRAC) - 1) & !((1 << FRAC) - 1))
+    }
+
+    /// The fractional part, self - self.floor().
+    pub const fn fract(self) -> Self
+    {
+        Fixed(self.0 & ((1 << FRAC) - 1))
+    }
+
+    pub const fn abs(self) -> Self
+    {
+        Fixed(self.0.wrapping_abs())
+    }
+
+    pub const fn signum(self) -> i32
+    {
+        self.0.signum()
+    }
+
+    /// Converts to a different number of fractional bits, rounding down when
+    /// bits are lost.
+    pub const fn convert<const TO: u32>(self) -> Fixed<TO>
+    {
+        if TO >= FRAC {
+            Fixed(self.0.wrapping_shl(TO - FRAC))
+        }
+        else {
+            Fixed(self.0 >> (FRAC - TO))
+        }
+    }
+
+    pub const fn saturating_add(self, rhs: Self) -> Self
+    {
+        Fixed(self.0.saturating_add(rhs.0))
+    }
+
+    pub const fn saturating_sub(self, rhs: Self) -> Self
+    {
+        Fixed(self.0.saturating_sub(rhs.0))
+    }
+
+    pub const fn saturating_mul(self, rhs: Self) -> Self
+    {
+        Fixed(saturate((self.0 as i64 * rhs.0 as i64) >> FRAC))
+    }
+
+    /// Division saturating on overflow. Division by zero saturates towards
+    /// the sign of self.
+    pub const fn saturating_div(self, rhs: Self) -> Self
+    {
+        if rhs.0 == 0 {
+            return if self.0 < 0 { Self::MIN } else { Self::MAX };
+        }
+        Fixed(saturate(((self.0 as i64) << FRAC) / rhs.0 as i64))
+    }
+
+    /// The square root, rounded down. Negative numbers give zero.
+    pub const fn sqrt(self) -> Self
+    {
+        if self.0 <= 0 {
+            return Self::ZERO;
+        }
+        Fixed(((self.0 as u64) << FRAC).isqrt() as i32)
+    }
+
+    /// For display and debugging only. Never feed the result back into core
+    /// logic.
+    pub fn to_f64(self) -> f64
+    {
+        self.0 as f64 / (1u64 << FRAC) as f64
+    }
+
+    pub fn serialize(self, state: &mut StateWriter)
+    {
+        state.write_i32(self.0);
+    }
+
+    pub fn unserialize(state: &mut StateReader) -> Self
+    {
+        Fixed(state.read_i32())
+    }
+}
+
+const fn saturate(value: i64) -> i32
+{
+    if value > i32::MAX as i64 {
+        i32::MAX
+    }
+    else if value < i32::MIN as i64 {
+        i32::MIN
+    }
+    else {
+        value as i32
+    }
+}
+
+impl<const FRAC: u32> Add for Fixed<FRAC>
+{
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self
+    {
+        Fixed(self.0.wrapping_add(rhs.0))
+    }
+}
+
+impl<const FRAC: u32> Sub for Fixed<FRAC>
+{
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self
+    {
+        Fixed(self.0.wrapping_sub(rhs.0))
+    }
+}
+
+impl<const FRAC: u32> Mul for Fixed<FRAC>
+{
+    type Output = Self;
+
+    /// Rounds down.
+    fn mul(self, rhs: Self) -> Self
+    {
+        Fixed(((self.0 as i64 * rhs.0 as i64) >> FRAC) as i32)
+    }
+}
+
+impl<const FRAC: u32> Mul<i32> for Fixed<FRAC>
+{
+    type Output = Self;
+
+    fn mul(self, rhs: i32) -> Self
+    {
+        Fixed(self.0.wrapping_mul(rhs))
+    }
+}
+
+impl<const FRAC: u32> Div for Fixed<FRAC>
+{
+    type Output = Self;
+
+    /// Rounds towards zero. Panics on division by zero.
+    fn div(self, rhs: Self) -> Self
+    {
+        Fixed((((self.0 as i64) << FRAC) / rhs.0 as i64) as i32)
+    }
+}
+
+impl<const FRAC: u32> Div<i32> for Fixed<FRAC>
+{
+    type Output = Self;
+
+    /// Rounds towards zero. Panics on division by zero.
+    fn div(self, rhs: i32) -> Self
+    {
+        Fixed(self.0.wrapping_div(rhs))
+    }
+}
+
+impl<const FRAC: u32> Neg for Fixed<FRAC>
+{
+    type Output = Self;
+
+    fn neg(self) -> Self
+    {
+        Fixed(self.0.wrapping_neg())
+    }
+}
+
+impl<const FRAC: u32> AddAssign for Fixed<FRAC>
+{
+    fn add_assign(&mut self, rhs: Self)
+    {
+        *self = *self + rhs;
+    }
+}
+
+impl<const FRAC: u32> SubAssign for Fixed<FRAC>
+{
+    fn sub_assign(&mut self, rhs: Self)
+    {
+        *self = *self - rhs;
+    }
+}
+
+impl<const FRAC: u32> MulAssign for Fixed<FRAC>
+{
+    fn mul_assign(&mut self, rhs: Self)
+    {
+        *self = *self * rhs;
+    }
+}
+
+impl<const FRAC: u32> DivAssign for Fixed<FRAC>
+{
+    fn div_assign(&mut self, rhs: Self)
+    {
+        *self = *self / rhs;
+    }
+}
+
+impl<const FRAC: u32> fmt::Debug for Fixed<FRAC>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        fmt::Display::fmt(&self.to_f64(), f)
+    }
+}
+
+impl<const FRAC: u32> fmt::Display for Fixed<FRAC>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        fmt::Display::fmt(&self.to_f64(), f)
+    }
+}
+
+
+/// A binary angle, where 65536 is a full turn, so angles wrap around
+/// naturally.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub struct Angle(pub u16);
+
+/// Lookup table steps per quarter turn.
+const TABLE_STEPS: usize = 1024;
+/// Angle units per lookup table step.
+const TABLE_STEP: u32 = 0x4000 / TABLE_STEPS as u32;
+
+/// sin over a quarter turn, in Q16_16.
+static SIN_TABLE: [i32; TABLE_STEPS + 1] = sin_table();
+/// atan of 0 to 1, in angle units.
+static ATAN_TABLE: [i32; TABLE_STEPS + 1] = atan_table();
+
+impl Angle
+{
+    pub const fn from_degrees(degrees: i32) -> Angle
+    {
+        Angle(((degrees.rem_euclid(360) as u32 * 0x10000 + 180) / 360) as u16)
+    }
+
+    /// Makes an angle from a fraction of a full turn.
+    pub const fn from_turns(turns: Q16_16) -> Angle
+    {
+        Angle(turns.raw() as u16)
+    }
+
+    pub fn sin(self) -> Q16_16
+    {
+        let quarter = self.0 >> 14;
+        let mut pos = (self.0 & 0x3fff) as u32;
+        if quarter & 1 != 0 {
+            pos = 0x4000 - pos;
+        }
+        let index = (pos / TABLE_STEP) as usize;
+        let step = (pos % TABLE_STEP) as i32;
+        let mut value = SIN_TABLE[index];
+        if step != 0 {
+            value += (SIN_TABLE[index + 1] - value) * step / TABLE_STEP as i32;
+        }
+        Fixed(if quarter >= 2 { -value } else { value })
+    }
+
+    pub fn cos(self) -> Q16_16
+    {
+        (self + Angle(0x4000)).sin()
+    }
+
+    /// The angle of the vector (x, y) from the x axis, counterclockwise for
+    /// positive y. Zero for (0, 0).
+    pub fn atan2<const FRAC: u32>(y: Fixed<FRAC>, x: Fixed<FRAC>) -> Angle
+    {
+        let (ax, ay) = ((x.0 as i64).abs(), (y.0 as i64).abs());
+        if ax == 0 && ay == 0 {
+            return Angle(0);
+        }
+        let octant_atan = |num: i64, den: i64| {
+            let ratio = (num * (TABLE_STEPS as i64) * TABLE_STEP as i64 / den) as i32;
+            let index = (ratio / TABLE_STEP as i32) as usize;
+            let step = ratio % TABLE_STEP as i32;
+            let mut value = ATAN_TABLE[index];
+            if step != 0 {
+                value += (ATAN_TABLE[index + 1] - value) * step / TABLE_STEP as i32;
+            }
+            value
+        };
+        let mut angle = if ay <= ax {
+            octant_atan(ay, ax)
+        }
+        else {
+            0x4000 - octant_atan(ax, ay)
+        };
+        if x.0 < 0 {
+            angle = 0x8000 - angle;
+        }
+        if y.0 < 0 {
+            angle = -angle;
+        }
+        Angle(angle as u16)
+    }
+
+    pub fn serialize(self, state: &mut StateWriter)
+    {
+        state.write_u16(self.0);
+    }
+
+    pub fn unserialize(state: &mut StateReader) -> Angle
+    {
+        Angle(state.read_u16())
+    }
+}
+
+impl Add for Angle
+{
+    type Output = Angle;
+
+    fn add(self, rhs: Angle) -> Angle
+    {
+        Angle(self.0.wrapping_add(rhs.0))
+    }
+}
+
+impl Sub for Angle
+{
+    type Output = Angle;
+
+    fn sub(self, rhs: Angle) -> Angle
+    {
+        Angle(self.0.wrapping_sub(rhs.0))
+    }
+}
+
+impl Neg for Angle
+{
+    type Output = Angle;
+
+    fn neg(self) -> Angle
+    {
+        Angle(self.0.wrapping_neg())
+    }
+}
+
+impl AddAssign for Angle
+{
+    fn add_assign(&mut self, rhs: Angle)
+    {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Angle
+{
+    fn sub_assign(&mut self, rhs: Angle)
+    {
+        *self = *self - rhs;
+    }
+}
+
+// The tables are computed with floating point at compile time, so they are
+// the same in every build, and rounded to the nearest unit.
+
+const fn sin_taylor(x: f64) -> f64
+{
+    let mut term = x;
+    let mut sum = x;
+    let mut n = 1;
+    while n < 12 {
+        term = -term * x * x / ((2 * n) as f64 * (2 * n + 1) as f64);
+        sum += term;
+        n += 1;
+    }
+    sum
+}
+
+const fn sqrt_newton(x: f64) -> f64
+{
+    let mut guess = if x > 1.0 { x } else { 1.0 };
+    let mut i = 0;
+    while i < 64 {
+        guess = (guess + x / guess) / 2.0;
+        i += 1;
+    }
+    guess
+}
+
+/// atan for 0 to 1, halving the argument twice with
+/// atan(x) = 2 atan(x / (1 + sqrt(1 + x^2))) so the series converges quickly.
+const fn atan_taylor(x: f64) -> f64
+{
+    let x = x / (1.0 + sqrt_newton(1.0 + x * x));
+    let x = x / (1.0 + sqrt_newton(1.0 + x * x));
+    let mut term = x;
+    let mut sum = x;
+    let mut n = 1;
+    while n < 20 {
+        term = -term * x * x;
+        sum += term / (2 * n + 1) as f64;
+        n += 1;
+    }
+    4.0 * sum
+}
+
+const fn sin_table() -> [i32; TABLE_STEPS + 1]
+{
+    let mut table = [0; TABLE_STEPS + 1];
+    let mut i = 0;
+    while i <= TABLE_STEPS {
+        let x = core::f64::consts::FRAC_PI_2 * i as f64 / TABLE_STEPS as f64;
+        table[i] = (sin_taylor(x) * 65536.0 + 0.5) as i32;
+        i += 1;
+    }
+    table
+}
+
+const fn atan_table() -> [i32; TABLE_STEPS + 1]
+{
+    let mut table = [0; TABLE_STEPS + 1];
+    let mut i = 0;
+    while i <= TABLE_STEPS {
+        let radians = atan_taylor(i as f64 / TABLE_STEPS as f64);
+        table[i] = (radians * 65536.0 / core::f64::consts::TAU + 0.5) as i32;
+        i += 1;
+    }
+    table
+}
+
+
+/// A 2D vector of fixed point numbers.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub struct Vec2<const FRAC: u32>
+{
+    pub x: Fixed<FRAC>,
+    pub y: Fixed<FRAC>,
+}
+
+/// A 3D vector of fixed point numbers.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub struct Vec3<const FRAC: u32>
+{
+    pub x: Fixed<FRAC>,
+    pub y: Fixed<FRAC>,
+    pub z: Fixed<FRAC>,
+}
+
+/// The square root of a sum of squared raw values, as a raw value. Exact
+/// up to rounding down, without overflow.
+fn raw_length(squares: u128) -> i32
+{
+    squares.isqrt().min(i32::MAX as u128) as i32
+}
+
+impl<const FRAC: u32> Vec2<FRAC>
+{
+    pub const ZERO: Self = Vec2 { x: Fixed::ZERO, y: Fixed::ZERO };
+
+    pub const fn new(x: Fixed<FRAC>, y: Fixed<FRAC>) -> Self
+    {
+        Vec2 { x, y }
+    }
+
+    /// A unit vector pointing at angle.
+    pub fn from_angle(angle: Angle) -> Self
+    {
+        Vec2 { x: angle.cos().convert(), y: angle.sin().convert() }
+    }
+
+    pub fn dot(self, rhs: Self) -> Fixed<FRAC>
+    {
+        Fixed(((self.x.0 as i64 * rhs.x.0 as i64 + self.y.0 as i64 * rhs.y.0 as i64) >> FRAC)
+              as i32)
+    }
+
+    /// The z component of the 3D cross product, positive when rhs is
+    /// counterclockwise from self.
+    pub fn cross(self, rhs: Self) -> Fixed<FRAC>
+    {
+        Fixed(((self.x.0 as i64 * rhs.y.0 as i64 - self.y.0 as i64 * rhs.x.0 as i64) >> FRAC)
+              as i32)
+    }
+
+    pub fn length(self) -> Fixed<FRAC>
+    {
+        let square = |v: Fixed<FRAC>| (v.0 as i64 * v.0 as i64) as u128;
+        Fixed(raw_length(square(self.x) + square(self.y)))
+    }
+
+    /// A vector of length one in the same direction, or zero for zero.
+    pub fn normalize(self) -> Self
+    {
+        let length = self.length();
+        if length.0 == 0 {
+            return Self::ZERO;
+        }
+        Vec2 { x: self.x.saturating_div(length), y: self.y.saturating_div(length) }
+    }
+
+    pub fn angle(self) -> Angle
+    {
+        Angle::atan2(self.y, self.x)
+    }
+
+    /// Rotated counterclockwise by angle.
+    pub fn rotate(self, angle: Angle) -> Self
+    {
+        let (sin, cos) = (angle.sin().0 as i64, angle.cos().0 as i64);
+        let (x, y) = (self.x.0 as i64, self.y.0 as i64);
+        Vec2 { x: Fixed(((x * cos - y * sin) >> 16) as i32),
+               y: Fixed(((x * sin + y * cos) >> 16) as i32) }
+    }
+
+    pub fn serialize(self, state: &mut StateWriter)
+    {
+        self.x.serialize(state);
+        self.y.serialize(state);
+    }
+
+    pub fn unserialize(state: &mut StateReader) -> Self
+    {
+        let x = Fixed::unserialize(state);
+        Vec2 { x, y: Fixed::unserialize(state) }
+    }
+}
+
+impl<const FRAC: u32> Vec3<FRAC>
+{
+    pub const ZERO: Self = Vec3 { x: Fixed::ZERO, y: Fixed::ZERO, z: Fixed::ZERO };
+
+    pub const fn new(x: Fixed<FRAC>, y: Fixed<FRAC>, z: Fixed<FRAC>) -> Self
+    {
+        Vec3 { x, y, z }
+    }
+
+    pub fn dot(self, rhs: Self) -> Fixed<FRAC>
+    {
+        Fixed(((self.x.0 as i64 * rhs.x.0 as i64 +
+                self.y.0 as i64 * rhs.y.0 as i64 +
+                self.z.0 as i64 * rhs.z.0 as i64) >> FRAC) as i32)
+    }
+
+    pub fn cross(self, rhs: Self) -> Self
+    {
+        let component = |a: Fixed<FRAC>, b: Fixed<FRAC>, c: Fixed<FRAC>, d: Fixed<FRAC>| {
+            Fixed(((a.0 as i64 * b.0 as i64 - c.0 as i64 * d.0 as i64) >> FRAC) as i32)
+        };
+        Vec3 { x: component(self.y, rhs.z, self.z, rhs.y),
+               y: component(self.z, rhs.x, self.x, rhs.z),
+               z: component(self.x, rhs.y, self.y, rhs.x) }
+    }
+
+    pub fn length(self) -> Fixed<FRAC>
+    {
+        let square = |v: Fixed<FRAC>| (v.0 as i64 * v.0 as i64) as u128;
+        Fixed(raw_length(square(self.x) + square(self.y) + square(self.z)))
+    }
+
+    /// A vector of length one in the same direction, or zero for zero.
+    pub fn normalize(self) -> Self
+    {
+        let length = self.length();
+        if length.0 == 0 {
+            return Self::ZERO;
+        }
+        Vec3 { x: self.x.saturating_div(length),
+               y: self.y.saturating_div(length),
+               z: self.z.saturating_div(length) }
+    }
+
+    pub fn serialize(self, state: &mut StateWriter)
+    {
+        self.x.serialize(state);
+        self.y.serialize(state);
+        self.z.serialize(state);
+    }
+
+    pub fn unserialize(state: &mut StateReader) -> Self
+    {
+        let x = Fixed::unserialize(state);
+        let y = Fixed::unserialize(state);
+        Vec3 { x, y, z: Fixed::unserialize(state) }
+    }
+}
+
+macro_rules! impl_vector_ops(
+    ($vec:ident { $($field:ident),+ }) => (
+        impl<const FRAC: u32> Add for $vec<FRAC>
+        {
+            type Output = Self;
+
+            fn add(self, rhs: Self) -> Self
+            {
+                $vec { $($field: self.$field + rhs.$field),+ }
+            }
+        }
+
+        impl<const FRAC: u32> Sub for $vec<FRAC>
+        {
+            type Output = Self;
+
+            fn sub(self, rhs: Self) -> Self
+            {
+                $vec { $($field: self.$field - rhs.$field),+ }
+            }
+        }
+
+        impl<const FRAC: u32> Neg for $vec<FRAC>
+        {
+            type Output = Self;
+
+            fn neg(self) -> Self
+            {
+                $vec { $($field: -self.$field),+ }
+            }
+        }
+
+        impl<const FRAC: u32> Mul<Fixed<FRAC>> for $vec<FRAC>
+        {
+            type Output = Self;
+
+            fn mul(self, rhs: Fixed<FRAC>) -> Self
+            {
+                $vec { $($field: self.$field * rhs),+ }
+            }
+        }
+
+        impl<const FRAC: u32> Div<Fixed<FRAC>> for $vec<FRAC>
+        {
+            type Output = Self;
+
+            fn div(self, rhs: Fixed<FRAC>) -> Self
+            {
+                $vec { $($field: self.$field / rhs),+ }
+            }
+        }
+
+        impl<const FRAC: u32> AddAssign for $vec<FRAC>
+        {
+            fn add_assign(&mut self, rhs: Self)
+            {
+                *self = *self + rhs;
+            }
+        }
+
+        impl<const FRAC: u32> SubAssign for $vec<FRAC>
+        {
+            fn sub_assign(&mut self, rhs: Self)
+            {
+                *self = *self - rhs;
+            }
+        }
+        );
+    );
+
+impl_vector_ops!(Vec2 { x, y });
+impl_vector_ops!(Vec3 { x, y, z });
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Nearest fixed point number, for checking conversions only.
+    fn from_f64<const FRAC: u32>(value: f64) -> Fixed<FRAC>
+    {
+        Fixed((value * (1u64 << FRAC) as f64).round() as i32)
+    }
+
+    #[test]
+    fn mul_rounds_down()
+    {
+        assert_eq!(Q16_16::from_ratio(3, 2) * Q16_16::from_ratio(5, 2), Q16_16::from_ratio(15, 4));
+        let half = Q16_16::from_ratio(1, 2);
+        assert_eq!(Q16_16::EPSILON * half, Q16_16::ZERO);
+        assert_eq!(-Q16_16::EPSILON * half, -Q16_16::EPSILON);
+        assert_eq!(Q24_8::from_raw(3) * Q24_8::from_raw(128), Q24_8::from_raw(1));
+        assert_eq!(Q24_8::from_raw(-3) * Q24_8::from_raw(128), Q24_8::from_raw(-2));
+        assert_eq!(Q24_8::from_int(-3) * 7, Q24_8::from_int(-21));
+    }
+
+    #[test]
+    fn div_rounds_towards_zero()
+    {
+        assert_eq!(Q16_16::ONE / Q16_16::from_int(3), Q16_16::from_raw(21845));
+        assert_eq!(-Q16_16::ONE / Q16_16::from_int(3), Q16_16::from_raw(-21845));
+        assert_eq!(Q24_8::from_int(1) / Q24_8::from_int(3), Q24_8::from_raw(85));
+        assert_eq!(Q24_8::from_raw(-7) / 2, Q24_8::from_raw(-3));
+        assert_eq!(Q16_16::from_ratio(-1, 3), Q16_16::from_raw(-21845));
+    }
+
+    #[test]
+    fn rounding()
+    {
+        assert_eq!(Q16_16::from_ratio(5, 2).round(), 3);
+        assert_eq!(Q16_16::from_ratio(-5, 2).round(), -2);
+        assert_eq!(Q16_16::from_ratio(-5, 2).to_int(), -3);
+        assert_eq!(Q16_16::from_ratio(-5, 2).floor(), Q16_16::from_int(-3));
+        assert_eq!(Q16_16::from_ratio(-5, 2).ceil(), Q16_16::from_int(-2));
+        assert_eq!(Q16_16::from_ratio(-5, 2).fract(), Q16_16::from_ratio(1, 2));
+        assert_eq!(Q16_16::from_ratio(3, 2).convert::<8>(), Q24_8::from_raw(384));
+        assert_eq!(Q24_8::from_raw(-1).convert::<16>(), Q16_16::from_raw(-256));
+        assert_eq!(Q16_16::from_raw(-1).convert::<8>(), Q24_8::from_raw(-1));
+    }
+
+    #[test]
+    fn overflow_wraps()
+    {
+        assert_eq!(Q16_16::MAX + Q16_16::EPSILON, Q16_16::MIN);
+        assert_eq!(Q16_16::MIN - Q16_16::EPSILON, Q16_16::MAX);
+        assert_eq!(-Q16_16::MIN, Q16_16::MIN);
+        assert_eq!(Q16_16::MIN.abs(), Q16_16::MIN);
+        assert_eq!(Q16_16::from_int(200) * Q16_16::from_int(200), Q16_16::from_raw(-1_673_527_296));
+        assert_eq!(Q24_8::MAX * 2, Q24_8::from_raw(-2));
+        assert_eq!(Q24_8::MIN / -1, Q24_8::MIN);
+    }
+
+    #[test]
+    fn saturating_at_the_edges()
+    {
+        assert_eq!(Q16_16::MAX.saturating_add(Q16_16::EPSILON), Q16_16::MAX);
+        assert_eq!(Q16_16::MIN.saturating_sub(Q16_16::EPSILON), Q16_16::MIN);
+        assert_eq!(Q16_16::from_int(200).saturating_mul(Q16_16::from_int(200)), Q16_16::MAX);
+        assert_eq!(Q16_16::from_int(-200).saturating_mul(Q16_16::from_int(200)), Q16_16::MIN);
+        assert_eq!(Q16_16::from_int(30000).saturating_div(Q16_16::EPSILON), Q16_16::MAX);
+        assert_eq!(Q16_16::ONE.saturating_div(Q16_16::ZERO), Q16_16::MAX);
+        assert_eq!((-Q16_16::ONE).saturating_div(Q16_16::ZERO), Q16_16::MIN);
+        assert_eq!(Q16_16::from_ratio(3, 2).saturating_mul(Q16_16::from_int(2)), Q16_16::from_int(3));
+    }
+
+    #[test]
+    fn sqrt_rounds_down()
+    {
+        assert_eq!(Q16_16::from_int(9).sqrt(), Q16_16::from_int(3));
+        assert_eq!(Q16_16::from_int(2).sqrt(), Q16_16::from_raw(92681));
+        assert_eq!(Q24_8::MAX.sqrt(), Q24_8::from_raw(741455));
+        assert_eq!(Q16_16::from_int(-4).sqrt(), Q16_16::ZERO);
+    }
+
+    #[test]
+    fn sin_cos_at_quadrant_boundaries()
+    {
+        let quarters = [(0x0000, 0, 1), (0x4000, 1, 0), (0x8000, 0, -1), (0xc000, -1, 0)];
+        for (angle, sin, cos) in quarters {
+            assert_eq!(Angle(angle).sin(), Q16_16::from_int(sin), "sin {:#x}", angle);
+            assert_eq!(Angle(angle).cos(), Q16_16::from_int(cos), "cos {:#x}", angle);
+        }
+        assert_eq!(Angle::from_degrees(45).sin(), Q16_16::from_raw(46341));
+        assert_eq!(Angle::from_degrees(45).cos(), Q16_16::from_raw(46341));
+        assert_eq!(Angle::from_degrees(-90), Angle(0xc000));
+    }
+
+    #[test]
+    fn sin_is_symmetric()
+    {
+        for angle in 0..=u16::MAX {
+            let angle = Angle(angle);
+            assert_eq!((-angle).sin(), -angle.sin());
+            assert_eq!((angle + Angle(0x8000)).sin(), -angle.sin());
+            assert_eq!((Angle(0x8000) - angle).sin(), angle.sin());
+            let exact = (angle.0 as f64 * core::f64::consts::TAU / 65536.0).sin();
+            let error = (angle.sin().to_f64() - exact).abs();
+            assert!(error < 2.0 / 65536.0, "{:?} off by {}", angle, error);
+        }
+    }
+
+    #[test]
+    fn atan2_quadrants()
+    {
+        let one = Q16_16::ONE;
+        assert_eq!(Angle::atan2(Q16_16::ZERO, one), Angle(0));
+        assert_eq!(Angle::atan2(one, one), Angle(0x2000));
+        assert_eq!(Angle::atan2(one, Q16_16::ZERO), Angle(0x4000));
+        assert_eq!(Angle::atan2(Q16_16::ZERO, -one), Angle(0x8000));
+        assert_eq!(Angle::atan2(-one, Q16_16::ZERO), Angle(0xc000));
+        assert_eq!(Angle::atan2(-one, -one), Angle(0xa000));
+        assert_eq!(Angle::atan2(Q16_16::ZERO, Q16_16::ZERO), Angle(0));
+        for degrees in (0..360).step_by(15) {
+            let angle = Angle::from_degrees(degrees);
+            let found = Angle::atan2(angle.sin(), angle.cos());
+            assert!((found - angle).0.wrapping_add(2) <= 4, "{} degrees gave {:?}", degrees, found);
+        }
+    }
+
+    #[test]
+    fn float_round_trips()
+    {
+        for raw in [i32::MIN, -65536, -257, -1, 0, 1, 255, 12345, 65536, i32::MAX] {
+            assert_eq!(from_f64::<16>(Q16_16::from_raw(raw).to_f64()).raw(), raw);
+            assert_eq!(from_f64::<8>(Q24_8::from_raw(raw).to_f64()).raw(), raw);
+        }
+        for value in [0.0, 0.5, -0.5, 1.0 / 3.0, core::f64::consts::PI, -1234.5678] {
+            assert!((from_f64::<16>(value).to_f64() - value).abs() <= 0.5 / 65536.0);
+            assert!((from_f64::<8>(value).to_f64() - value).abs() <= 0.5 / 256.0);
+        }
+        assert_eq!(Q16_16::from_ratio(-3, 4).to_f64(), -0.75);
+        assert_eq!(format!("{}", Q24_8::from_ratio(5, 2)), "2.5");
+    }
+}
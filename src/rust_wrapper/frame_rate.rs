@@ -0,0 +1,500 @@
+//! The automatic frame rate core option and frame pacing.
+//!
+//! Video frames need not divide the core logic rate evenly. Each frame runs the
+//! number of core logic updates that keeps the total in step with the frame
+//! rate, so a 720Hz core at 59.94fps alternates between 12 and 13 updates per
+//! frame. The count for each frame depends only on its index, counted from
+//! when the frame rate was selected, so input recordings replay identically.
+//!
+//! The default "auto" frame rate is the one closest to the refresh rate the
+//! frontend reports it is targeting, or closest to 60fps, or 50fps for PAL
+//! content, if it doesn't say.
+//!
+//! The frame pacing option can instead run as many updates as the frontend's
+//! frame time callback says have passed in real time. This follows displays
+//! whose refresh rate drifts from the selected frame rate, at the cost of
+//! replays no longer matching.
+
+use core::ffi::{c_float, c_void};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+
+use super::libretro::*;
+use super::panic::catch_panic;
+use super::{current_fps, environment, get_variable, notify, region};
+
+/// Range of frame rates offered, in frames per second.
+const MIN_FPS: u32 = 24;
+const MAX_FPS: u32 = 240;
+/// Display refresh rates offered even when they don't divide the core logic
+/// rate, as labels and exact frames per second fractions.
+const COMMON_RATES: &[(&str, u64, u64)] = &[("24", 24, 1),
+                                            ("25", 25, 1),
+                                            ("29.97", 30000, 1001),
+                                            ("30", 30, 1),
+                                            ("48", 48, 1),
+                                            ("50", 50, 1),
+                                            ("59.94", 60000, 1001),
+                                            ("60", 60, 1),
+                                            ("72", 72, 1),
+                                            ("75", 75, 1),
+                                            ("90", 90, 1),
+                                            ("100", 100, 1),
+                                            ("119.88", 120000, 1001),
+                                            ("120", 120, 1),
+                                            ("144", 144, 1),
+                                            ("165", 165, 1),
+                                            ("240", 240, 1)];
+
+pub const FRAME_RATE_KEY: &str = "frame_rate\0";
+const AUTO_FRAME_RATE: &str = "auto";
+const FRAME_PACING_KEY: &str = "frame_pacing\0";
+const REAL_TIME_PACING: &str = "real time";
+
+/// Longest frame time honoured by real time pacing. Longer frames, from the
+/// frontend pausing or stalling, drop the excess updates rather than running
+/// them all in one burst.
+const MAX_FRAME_USEC: i64 = 100_000;
+
+/// A video frame rate of num / den frames per second.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FrameRate
+{
+    num: u64,
+    den: u64,
+}
+
+impl FrameRate
+{
+    pub fn fps(self) -> f64
+    {
+        self.num as f64 / self.den as f64
+    }
+
+    /// Whether every frame runs the same number of logic updates.
+    pub fn is_exact(self) -> bool
+    {
+        (region::core_logic_rate() as u64 * self.den).is_multiple_of(self.num)
+    }
+
+    /// Logic updates run before the given frame since the rate was selected.
+    fn ticks_before(self, logic_rate: u32, frame: u64) -> u64
+    {
+        (frame as u128 * logic_rate as u128 * self.den as u128 / self.num as u128) as u64
+    }
+
+    /// Logic updates to run in the given frame. At least one, as frame rates
+    /// never exceed the core logic rate.
+    pub fn ticks_for_frame(self, frame: u64) -> u32
+    {
+        self.ticks_at(region::core_logic_rate(), frame)
+    }
+
+    fn ticks_at(self, logic_rate: u32, frame: u64) -> u32
+    {
+        (self.ticks_before(logic_rate, frame + 1) - self.ticks_before(logic_rate, frame)) as u32
+    }
+
+    fn label(self) -> String
+    {
+        match COMMON_RATES.iter().find(|&&(_, num, den)| self == FrameRate { num, den }) {
+            Some(&(label, _, _)) => label.to_string(),
+            None => format!("{}", self.num / self.den),
+        }
+    }
+}
+
+impl PartialOrd for FrameRate
+{
+    fn partial_cmp(&self, other: &FrameRate) -> Option<std::cmp::Ordering>
+    {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FrameRate
+{
+    fn cmp(&self, other: &FrameRate) -> std::cmp::Ordering
+    {
+        (self.num as u128 * other.den as u128).cmp(&(other.num as u128 * self.den as u128))
+    }
+}
+
+/// Frame rates offered by the frame rate option, the default first, then the
+/// higher frame rates in ascending order, then the lower ones. Cores that
+/// may run PAL content offer the frame rates suiting either logic rate.
+pub fn frame_rates() -> Vec<FrameRate>
+{
+    frame_rates_for(&region::possible_logic_rates(), region::region().fps() as f64)
+}
+
+/// frame_rates() for the given logic rates, defaulting to the frame rate
+/// closest to default_fps.
+fn frame_rates_for(logic_rates: &[u32], default_fps: f64) -> Vec<FrameRate>
+{
+    let lowest = *logic_rates.iter().min().unwrap();
+    let logic_rate = FrameRate { num: lowest as u64, den: 1 };
+    let min = FrameRate { num: MIN_FPS as u64, den: 1 };
+    let max = FrameRate { num: MAX_FPS as u64, den: 1 };
+
+    let mut rates: Vec<FrameRate> = logic_rates.iter()
+        .flat_map(|&rate| {
+            (1..=rate).filter(move |&mult| rate.is_multiple_of(mult))
+                .map(move |mult| FrameRate { num: (rate / mult) as u64, den: 1 })
+        })
+        .chain(COMMON_RATES.iter().map(|&(_, num, den)| FrameRate { num, den }))
+        .filter(|&rate| rate >= min && rate <= max && rate <= logic_rate)
+        .collect();
+    if rates.is_empty() {
+        // No usable frame rate, so run one update per frame
+        rates.push(logic_rate);
+    }
+    rates.sort_unstable();
+    rates.dedup();
+
+    let default = closest(&rates, default_fps);
+    let mut ordered = vec![default];
+    ordered.extend(rates.iter().filter(|&&rate| rate > default));
+    ordered.extend(rates.iter().filter(|&&rate| rate < default));
+    ordered
+}
+
+/// The frame rate closest to fps, ties going to the higher frame rate.
+fn closest(rates: &[FrameRate], fps: f64) -> FrameRate
+{
+    *rates.iter()
+        .min_by(|a, b| {
+            let distance = |rate: &FrameRate| (rate.fps() - fps).abs();
+            distance(a).total_cmp(&distance(b)).then(b.cmp(a))
+        })
+        .unwrap()
+}
+
+/// The refresh rate the frontend is targeting, if it says.
+fn target_refresh_rate() -> Option<c_float>
+{
+    let mut refresh_rate: c_float = 0.0;
+    environment(RETRO_ENVIRONMENT_GET_TARGET_REFRESH_RATE,
+                &mut refresh_rate as *mut c_float as *mut c_void)
+        .then_some(refresh_rate)
+}
+
+/// A refresh rate from the frontend, unless it's zero, negative or not finite.
+fn usable_refresh_rate(refresh_rate: Option<c_float>) -> Option<f64>
+{
+    refresh_rate.filter(|rate| rate.is_finite() && *rate > 0.0).map(f64::from)
+}
+
+/// Builds the frame rate and frame pacing options as null terminated key and
+/// value strings for RETRO_ENVIRONMENT_SET_VARIABLES.
+pub fn variables() -> Vec<(String, String)>
+{
+    let values: Vec<String> = frame_rates().into_iter()
+        .map(FrameRate::label)
+        .collect();
+    vec![(FRAME_RATE_KEY.to_string(),
+          format!("Frame rate; {}|{}\0", AUTO_FRAME_RATE, values.join("|"))),
+         (FRAME_PACING_KEY.to_string(),
+          format!("Frame pacing; fixed|{}\0", REAL_TIME_PACING))]
+}
+
+/// Reads the frame pacing option. Called whenever the frontend reports a core
+/// option change.
+pub fn load_options()
+{
+    let real_time = get_variable(FRAME_PACING_KEY).as_deref() == Some(REAL_TIME_PACING);
+    if REAL_TIME.swap(real_time, Ordering::Relaxed) != real_time {
+        reset_pacing();
+    }
+}
+
+/// Maps a frame rate option value to its frame rate. A missing value means
+/// auto, and an unrecognised one falls back to the default frame rate.
+pub fn frame_rate_for(value: Option<&str>) -> FrameRate
+{
+    let value = value.unwrap_or(AUTO_FRAME_RATE);
+    let refresh_rate = if value == AUTO_FRAME_RATE {
+        let refresh_rate = target_refresh_rate();
+        match usable_refresh_rate(refresh_rate) {
+            Some(refresh_rate) => log_info!("Frontend refresh rate is {:.2} Hz", refresh_rate),
+            None => log_info!("Frontend didn't report a refresh rate, using the default frame rate"),
+        }
+        refresh_rate
+    } else {
+        None
+    };
+    match select_frame_rate(value, &frame_rates(), refresh_rate, region::region().fps() as f64) {
+        Ok(rate) => rate,
+        Err(default) => {
+            log_warn!("Unsupported frame rate option {:?}, using {} fps",
+                      value, default.label());
+            notify("Unsupported frame rate option", 3.0);
+            default
+        }
+    }
+}
+
+/// Picks the frame rate for an option value from rates. Auto picks the one
+/// closest to the frontend's refresh rate, or to default_fps without a usable
+/// one. An unrecognised value gives an error holding the frame rate closest
+/// to default_fps.
+fn select_frame_rate(value: &str, rates: &[FrameRate], refresh_rate: Option<c_float>,
+                     default_fps: f64) -> Result<FrameRate, FrameRate>
+{
+    let default = closest(rates, default_fps);
+    if value == AUTO_FRAME_RATE {
+        return Ok(usable_refresh_rate(refresh_rate).map_or(default, |fps| closest(rates, fps)));
+    }
+    rates.iter().find(|rate| rate.label() == value).copied().ok_or(default)
+}
+
+/// Video frames run since the frame rate was selected or the core reset.
+static PACING_FRAME: AtomicU64 = AtomicU64::new(0);
+/// Whether the frame pacing option is set to real time.
+static REAL_TIME: AtomicBool = AtomicBool::new(false);
+/// Time since the last frame reported by the frame time callback, or -1 if
+/// the frontend doesn't call it.
+static FRAME_USEC: AtomicI64 = AtomicI64::new(-1);
+/// Elapsed time not yet run by real time pacing, in millionths of an update.
+static TICK_REMAINDER: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of logic updates to run in the next video frame.
+pub fn next_frame_ticks(rate: FrameRate) -> u32
+{
+    let frame = PACING_FRAME.fetch_add(1, Ordering::Relaxed);
+    let usec = FRAME_USEC.load(Ordering::Relaxed);
+    if !REAL_TIME.load(Ordering::Relaxed) || usec < 0 {
+        return rate.ticks_for_frame(frame);
+    }
+    real_time_ticks(usec)
+}
+
+/// Logic updates covering usec microseconds, carrying the fraction of an
+/// update left over to the next frame.
+fn real_time_ticks(usec: i64) -> u32
+{
+    let logic_rate = region::core_logic_rate() as u64;
+    let (ticks, remainder) = elapsed_ticks(usec, logic_rate, TICK_REMAINDER.load(Ordering::Relaxed));
+    TICK_REMAINDER.store(remainder, Ordering::Relaxed);
+
+    if usec > MAX_FRAME_USEC {
+        let dropped = (usec - MAX_FRAME_USEC) as u64 * logic_rate / 1_000_000;
+        log_warn!("Frame took {} ms, dropped {} updates", usec / 1000, dropped);
+    }
+    else if ticks == 0 {
+        log_debug!("Frame took {} us, no update due, repeating the last frame", usec);
+    }
+    ticks
+}
+
+/// Logic updates covering usec microseconds, at most MAX_FRAME_USEC, plus the
+/// remainder carried from earlier frames, in millionths of an update. Returns
+/// the updates and the new remainder.
+fn elapsed_ticks(usec: i64, logic_rate: u64, remainder: u64) -> (u32, u64)
+{
+    let elapsed = usec.clamp(0, MAX_FRAME_USEC) as u64 * logic_rate + remainder;
+    ((elapsed / 1_000_000) as u32, elapsed % 1_000_000)
+}
+
+/// Restarts the tick schedule, for a new frame rate or a reset core.
+pub fn reset_pacing()
+{
+    PACING_FRAME.store(0, Ordering::Relaxed);
+    TICK_REMAINDER.store(0, Ordering::Relaxed);
+}
+
+extern "C" fn frame_time_callback(usec: retro_usec_t)
+{
+    catch_panic("frame_time_callback", (), || FRAME_USEC.store(usec, Ordering::Relaxed));
+}
+
+/// Registers the frame time callback used by real time pacing, with the
+/// reference frame time for the current frame rate. Sent when content is
+/// loaded and again whenever the frame rate changes.
+pub fn set_frame_time_callback()
+{
+    let mut callback = retro_frame_time_callback {
+        callback: frame_time_callback,
+        reference: (1_000_000.0 / current_fps()).round() as retro_usec_t,
+    };
+    if !environment(RETRO_ENVIRONMENT_SET_FRAME_TIME_CALLBACK,
+                    &mut callback as *mut _ as *mut c_void) {
+        log_info!("Frontend has no frame time callback, real time pacing unavailable");
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn rate(num: u64, den: u64) -> FrameRate
+    {
+        FrameRate { num, den }
+    }
+
+    #[test]
+    fn ntsc_rate_alternates_ticks()
+    {
+        let ntsc = rate(60000, 1001);
+        let ticks: Vec<u32> = (0..1001).map(|frame| ntsc.ticks_at(720, frame)).collect();
+        assert!(ticks.iter().all(|&ticks| ticks == 12 || ticks == 13));
+        assert!(ticks.contains(&12) && ticks.contains(&13));
+        // 720 * 1001 / 60000 = 12.012 updates per frame on average
+        assert_eq!(ticks.iter().filter(|&&ticks| ticks == 13).count(), 12);
+        for frames in [0, 1, 83, 84, 500, 1001] {
+            let sum: u64 = ticks[..frames].iter().map(|&ticks| ticks as u64).sum();
+            assert_eq!(sum, ntsc.ticks_before(720, frames as u64));
+        }
+        assert_eq!(ntsc.ticks_before(720, 1000), 12012);
+    }
+
+    fn labels(logic_rates: &[u32], default_fps: f64) -> Vec<String>
+    {
+        frame_rates_for(logic_rates, default_fps).into_iter().map(FrameRate::label).collect()
+    }
+
+    #[test]
+    fn rates_for_60hz()
+    {
+        assert_eq!(labels(&[60], 60.0),
+                   ["60", "24", "25", "29.97", "30", "48", "50", "59.94"]);
+    }
+
+    #[test]
+    fn rates_for_120hz()
+    {
+        assert_eq!(labels(&[120], 60.0),
+                   ["60", "72", "75", "90", "100", "119.88", "120",
+                    "24", "25", "29.97", "30", "40", "48", "50", "59.94"]);
+    }
+
+    #[test]
+    fn rates_for_720hz()
+    {
+        assert_eq!(labels(&[720], 60.0),
+                   ["60", "72", "75", "80", "90", "100", "119.88", "120", "144", "165", "180", "240",
+                    "24", "25", "29.97", "30", "36", "40", "45", "48", "50", "59.94"]);
+    }
+
+    #[test]
+    fn rates_for_ntsc_and_pal()
+    {
+        let ntsc = frame_rates_for(&[720, 600], 60.0);
+        let pal = frame_rates_for(&[720, 600], 50.0);
+        assert_eq!(ntsc[0], rate(60, 1));
+        assert_eq!(pal[0], rate(50, 1));
+        let mut sorted = pal.clone();
+        sorted.sort();
+        let mut ntsc_sorted = ntsc.clone();
+        ntsc_sorted.sort();
+        assert_eq!(sorted, ntsc_sorted);
+        // Divisors of either logic rate, besides the common rates
+        for fps in [150, 200, 36, 80] {
+            assert!(ntsc.contains(&rate(fps, 1)), "{} fps missing", fps);
+        }
+        // Only within MIN_FPS and MAX_FPS
+        assert!(ntsc.iter().all(|&fps| fps >= rate(MIN_FPS as u64, 1) && fps <= rate(MAX_FPS as u64, 1)));
+        assert!(!ntsc.contains(&rate(20, 1)) && !ntsc.contains(&rate(300, 1)));
+    }
+
+    #[test]
+    fn rates_are_ordered_without_duplicates()
+    {
+        for logic_rates in [&[60][..], &[120], &[720], &[720, 600]] {
+            let rates = frame_rates_for(logic_rates, 60.0);
+            let (default, rest) = rates.split_first().unwrap();
+            let higher: Vec<_> = rest.iter().take_while(|&rate| rate > default).collect();
+            let lower = &rest[higher.len()..];
+            // Strictly ascending, so rates that are both divisors and common
+            // rates are only offered once
+            assert!(higher.windows(2).all(|pair| pair[0] < pair[1]));
+            assert!(lower.windows(2).all(|pair| pair[0] < pair[1]));
+            assert!(lower.iter().all(|rate| rate < default));
+        }
+    }
+
+    #[test]
+    fn no_usable_rate()
+    {
+        assert_eq!(frame_rates_for(&[10], 60.0), [rate(10, 1)]);
+    }
+
+    #[test]
+    fn real_time_remainder_carries_over()
+    {
+        // 16683us is a 59.94Hz display's frame, 12.01176 updates at 720Hz
+        let mut remainder = 0;
+        let mut total = 0;
+        for frame in 1..=1000u64 {
+            let (ticks, left) = elapsed_ticks(16683, 720, remainder);
+            assert!(ticks == 12 || ticks == 13);
+            remainder = left;
+            total += ticks as u64;
+            assert_eq!(total * 1_000_000 + remainder, frame * 16683 * 720);
+        }
+        assert_eq!(total, 12011);
+        assert_eq!(elapsed_ticks(500, 720, 999_999), (1, 359_999));
+    }
+
+    #[test]
+    fn real_time_clamps_frame_time()
+    {
+        assert_eq!(elapsed_ticks(MAX_FRAME_USEC, 720, 0), (72, 0));
+        assert_eq!(elapsed_ticks(10_000_000, 720, 0), (72, 0));
+        assert_eq!(elapsed_ticks(i64::MAX, 720, 5), (72, 5));
+        assert_eq!(elapsed_ticks(-1, 720, 0), (0, 0));
+        assert_eq!(elapsed_ticks(i64::MIN, 720, 123), (0, 123));
+        assert_eq!(elapsed_ticks(0, 60, 400_000), (0, 400_000));
+    }
+
+    #[test]
+    fn auto_follows_the_refresh_rate()
+    {
+        let rates = frame_rates_for(&[720], 60.0);
+        let auto = |refresh_rate| select_frame_rate(AUTO_FRAME_RATE, &rates, refresh_rate, 60.0);
+        assert_eq!(auto(Some(59.94)), Ok(rate(60000, 1001)));
+        assert_eq!(auto(Some(143.9)), Ok(rate(144, 1)));
+        assert_eq!(auto(Some(1000.0)), Ok(rate(240, 1)));
+        for unusable in [None, Some(0.0), Some(-60.0), Some(f32::NAN), Some(f32::INFINITY)] {
+            assert_eq!(auto(unusable), Ok(rate(60, 1)), "{:?}", unusable);
+        }
+        let pal = select_frame_rate(AUTO_FRAME_RATE, &rates, None, 50.0);
+        assert_eq!(pal, Ok(rate(50, 1)));
+    }
+
+    #[test]
+    fn listed_and_unknown_values()
+    {
+        let rates = frame_rates_for(&[720], 60.0);
+        assert_eq!(select_frame_rate("75", &rates, None, 60.0), Ok(rate(75, 1)));
+        assert_eq!(select_frame_rate("119.88", &rates, Some(60.0), 60.0), Ok(rate(120000, 1001)));
+        // The refresh rate only matters for auto
+        assert_eq!(select_frame_rate("30", &rates, Some(144.0), 60.0), Ok(rate(30, 1)));
+        for unknown in ["", "61", "23.976", "Auto", "60 "] {
+            assert_eq!(select_frame_rate(unknown, &rates, None, 60.0), Err(rate(60, 1)));
+        }
+        assert_eq!(select_frame_rate("7", &rates, None, 50.0), Err(rate(50, 1)));
+    }
+
+    #[test]
+    fn closest_breaks_ties_upwards()
+    {
+        let rates = [rate(24, 1), rate(25, 1), rate(50, 1), rate(60, 1)];
+        assert_eq!(closest(&rates, 24.5), rate(25, 1));
+        assert_eq!(closest(&rates, 55.0), rate(60, 1));
+        assert_eq!(closest(&rates, 54.9), rate(50, 1));
+        assert_eq!(closest(&rates, 1000.0), rate(60, 1));
+        assert_eq!(closest(&rates, 0.0), rate(24, 1));
+    }
+
+    #[test]
+    fn exact_rates_run_constant_ticks()
+    {
+        for (logic_rate, fps, expected) in [(720, 60, 12), (720, 240, 3), (720, 24, 30),
+                                            (600, 50, 12), (60, 60, 1), (120, 30, 4)] {
+            for frame in [0, 1, 2, 1000, 1 << 40] {
+                assert_eq!(rate(fps, 1).ticks_at(logic_rate, frame), expected);
+            }
+        }
+    }
+}
@@ -1,4 +1,8 @@
-use super::retro_input_state_cb;
+use core::ffi::c_uint;
+use core::ops::Index;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use super::{check, get_variable, movie, region, retro_input_poll_cb, retro_input_state_cb};
 use super::libretro::RETRO_DEVICE_ID_JOYPAD_B;
 use super::libretro::RETRO_DEVICE_ID_JOYPAD_Y;
 use super::libretro::RETRO_DEVICE_ID_JOYPAD_SELECT;
@@ -16,72 +20,59 @@ use super::libretro::RETRO_DEVICE_ID_JOYPAD_R2;
 use super::libretro::RETRO_DEVICE_ID_JOYPAD_L3;
 use super::libretro::RETRO_DEVICE_ID_JOYPAD_R3;
 use super::libretro::RETRO_DEVICE_JOYPAD;
-use core::prelude::*;
+use self::ControllerButton::*;
 
 // WARNING
 // Don't change without also changing InputState and static asserts
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum ControllerButton
 {
-    PadB = RETRO_DEVICE_ID_JOYPAD_B as int,
-    PadY = RETRO_DEVICE_ID_JOYPAD_Y as int,
-    PadSelect = RETRO_DEVICE_ID_JOYPAD_SELECT as int,
-    PadStart = RETRO_DEVICE_ID_JOYPAD_START as int,
-    PadUp = RETRO_DEVICE_ID_JOYPAD_UP as int,
-    PadDown = RETRO_DEVICE_ID_JOYPAD_DOWN as int,
-    PadLeft = RETRO_DEVICE_ID_JOYPAD_LEFT as int,
-    PadRight = RETRO_DEVICE_ID_JOYPAD_RIGHT as int,
-    PadA = RETRO_DEVICE_ID_JOYPAD_A as int,
-    PadX = RETRO_DEVICE_ID_JOYPAD_X as int,
-    PadL = RETRO_DEVICE_ID_JOYPAD_L as int,
-    PadR = RETRO_DEVICE_ID_JOYPAD_R as int,
-    PadL2 = RETRO_DEVICE_ID_JOYPAD_L2 as int,
-    PadR2 = RETRO_DEVICE_ID_JOYPAD_R2 as int,
-    PadL3 = RETRO_DEVICE_ID_JOYPAD_L3 as int,
-    PadR3 = RETRO_DEVICE_ID_JOYPAD_R3 as int,
+    PadB = RETRO_DEVICE_ID_JOYPAD_B as isize,
+    PadY = RETRO_DEVICE_ID_JOYPAD_Y as isize,
+    PadSelect = RETRO_DEVICE_ID_JOYPAD_SELECT as isize,
+    PadStart = RETRO_DEVICE_ID_JOYPAD_START as isize,
+    PadUp = RETRO_DEVICE_ID_JOYPAD_UP as isize,
+    PadDown = RETRO_DEVICE_ID_JOYPAD_DOWN as isize,
+    PadLeft = RETRO_DEVICE_ID_JOYPAD_LEFT as isize,
+    PadRight = RETRO_DEVICE_ID_JOYPAD_RIGHT as isize,
+    PadA = RETRO_DEVICE_ID_JOYPAD_A as isize,
+    PadX = RETRO_DEVICE_ID_JOYPAD_X as isize,
+    PadL = RETRO_DEVICE_ID_JOYPAD_L as isize,
+    PadR = RETRO_DEVICE_ID_JOYPAD_R as isize,
+    PadL2 = RETRO_DEVICE_ID_JOYPAD_L2 as isize,
+    PadR2 = RETRO_DEVICE_ID_JOYPAD_R2 as isize,
+    PadL3 = RETRO_DEVICE_ID_JOYPAD_L3 as isize,
+    PadR3 = RETRO_DEVICE_ID_JOYPAD_R3 as isize,
 }
 
-#[static_assert]
-static _I0: bool = (PadB as int == 0);
-#[static_assert]
-static _I1: bool = (PadY as int == 1);
-#[static_assert]
-static _I2: bool = (PadSelect as int == 2);
-#[static_assert]
-static _I3: bool = (PadStart as int == 3);
-#[static_assert]
-static _I4: bool = (PadUp as int == 4);
-#[static_assert]
-static _I5: bool = (PadDown as int == 5);
-#[static_assert]
-static _I6: bool = (PadLeft as int == 6);
-#[static_assert]
-static _I7: bool = (PadRight as int == 7);
-#[static_assert]
-static _I8: bool = (PadA as int == 8);
-#[static_assert]
-static _I9: bool = (PadX as int == 9);
-#[static_assert]
-static _I10: bool = (PadL as int == 10);
-#[static_assert]
-static _I11: bool = (PadR as int == 11);
-#[static_assert]
-static _I12: bool = (PadL2 as int == 12);
-#[static_assert]
-static _I13: bool = (PadR2 as int == 13);
-#[static_assert]
-static _I14: bool = (PadL3 as int == 14);
-#[static_assert]
-static _I15: bool = (PadR3 as int == 15);
+const _: () = assert!(PadB as usize == 0);
+const _: () = assert!(PadY as usize == 1);
+const _: () = assert!(PadSelect as usize == 2);
+const _: () = assert!(PadStart as usize == 3);
+const _: () = assert!(PadUp as usize == 4);
+const _: () = assert!(PadDown as usize == 5);
+const _: () = assert!(PadLeft as usize == 6);
+const _: () = assert!(PadRight as usize == 7);
+const _: () = assert!(PadA as usize == 8);
+const _: () = assert!(PadX as usize == 9);
+const _: () = assert!(PadL as usize == 10);
+const _: () = assert!(PadR as usize == 11);
+const _: () = assert!(PadL2 as usize == 12);
+const _: () = assert!(PadR2 as usize == 13);
+const _: () = assert!(PadL3 as usize == 14);
+const _: () = assert!(PadR3 as usize == 15);
 
+#[derive(Clone, Copy)]
 pub struct InputState
 {
     // WARNING
     // Don't change size without also changing ControllerButton
     // and static asserts
-    pub button: [ButtonState, ..16]
+    pub button: [ButtonState; 16]
 }
 
+#[derive(Clone, Copy, Default)]
 pub struct ButtonState
 {
     pub pressed: bool,
@@ -89,11 +80,13 @@ pub struct ButtonState
     pub up: bool
 }
 
-impl Index<ControllerButton, ButtonState> for InputState
+impl Index<ControllerButton> for InputState
 {
-    fn index<'a>(&'a self, index: &ControllerButton) -> &'a ButtonState
+    type Output = ButtonState;
+
+    fn index(&self, index: ControllerButton) -> &ButtonState
     {
-        unsafe {self.button.unsafe_get(*index as uint)}
+        &self.button[index as usize]
     }
 }
 
@@ -102,32 +95,126 @@ impl InputState
     pub fn poll(player: u32) -> InputState
     {
         // assert!(player < 16, "Tried to poll input for invalid player number");
-        let state: InputState =
-        unsafe
-        {
-            InputState
-            {
-                button: [
-                    ButtonState {pressed: retro_input_state_cb.unwrap()(player, RETRO_DEVICE_JOYPAD, 0, PadB as u32) != 0, down: false, up: false},
-                    ButtonState {pressed: retro_input_state_cb.unwrap()(player, RETRO_DEVICE_JOYPAD, 0, PadY as u32) != 0, down: false, up: false},
-                    ButtonState {pressed: retro_input_state_cb.unwrap()(player, RETRO_DEVICE_JOYPAD, 0, PadSelect as u32) != 0, down: false, up: false},
-                    ButtonState {pressed: retro_input_state_cb.unwrap()(player, RETRO_DEVICE_JOYPAD, 0, PadStart as u32) != 0, down: false, up: false},
-                    ButtonState {pressed: retro_input_state_cb.unwrap()(player, RETRO_DEVICE_JOYPAD, 0, PadUp as u32) != 0, down: false, up: false},
-                    ButtonState {pressed: retro_input_state_cb.unwrap()(player, RETRO_DEVICE_JOYPAD, 0, PadDown as u32) != 0, down: false, up: false},
-                    ButtonState {pressed: retro_input_state_cb.unwrap()(player, RETRO_DEVICE_JOYPAD, 0, PadLeft as u32) != 0, down: false, up: false},
-                    ButtonState {pressed: retro_input_state_cb.unwrap()(player, RETRO_DEVICE_JOYPAD, 0, PadRight as u32) != 0, down: false, up: false},
-                    ButtonState {pressed: retro_input_state_cb.unwrap()(player, RETRO_DEVICE_JOYPAD, 0, PadA as u32) != 0, down: false, up: false},
-                    ButtonState {pressed: retro_input_state_cb.unwrap()(player, RETRO_DEVICE_JOYPAD, 0, PadX as u32) != 0, down: false, up: false},
-                    ButtonState {pressed: retro_input_state_cb.unwrap()(player, RETRO_DEVICE_JOYPAD, 0, PadL as u32) != 0, down: false, up: false},
-                    ButtonState {pressed: retro_input_state_cb.unwrap()(player, RETRO_DEVICE_JOYPAD, 0, PadR as u32) != 0, down: false, up: false},
-                    ButtonState {pressed: retro_input_state_cb.unwrap()(player, RETRO_DEVICE_JOYPAD, 0, PadL2 as u32) != 0, down: false, up: false},
-                    ButtonState {pressed: retro_input_state_cb.unwrap()(player, RETRO_DEVICE_JOYPAD, 0, PadR2 as u32) != 0, down: false, up: false},
-                    ButtonState {pressed: retro_input_state_cb.unwrap()(player, RETRO_DEVICE_JOYPAD, 0, PadL3 as u32) != 0, down: false, up: false},
-                    ButtonState {pressed: retro_input_state_cb.unwrap()(player, RETRO_DEVICE_JOYPAD, 0, PadR3 as u32) != 0, down: false, up: false},
-                    ]
-            }
+        if let Some(buttons) = check::replay_input(player) {
+            return InputState::from_bits(buttons);
+        }
+        let state = match movie::playback(player) {
+            Some(buttons) => InputState::from_bits(buttons),
+            None => InputState::poll_frontend(player),
         };
+        check::record_input(player, state.bits());
+        state
+    }
+
+    fn poll_frontend(player: u32) -> InputState
+    {
+        let input_state = unsafe { retro_input_state_cb.unwrap() };
+        let mut state = InputState { button: [ButtonState::default(); 16] };
+        // ControllerButton discriminants are the libretro joypad ids
+        for (id, button) in state.button.iter_mut().enumerate() {
+            button.pressed =
+                input_state(player, RETRO_DEVICE_JOYPAD, 0, id as c_uint) != 0;
+        }
         // TODO track state changes and update down and up fields
-        state      
+        movie::record(player, state.bits());
+        state
+    }
+
+    /// The pressed buttons as a bitmask indexed by ControllerButton.
+    fn bits(&self) -> u16
+    {
+        self.button.iter().enumerate()
+            .filter(|(_, button)| button.pressed)
+            .fold(0, |bits, (id, _)| bits | 1 << id)
+    }
+
+    fn from_bits(bits: u16) -> InputState
+    {
+        let mut state = InputState { button: [ButtonState::default(); 16] };
+        for (id, button) in state.button.iter_mut().enumerate() {
+            button.pressed = bits & 1 << id != 0;
+        }
+        state
+    }
+}
+
+const INPUT_POLLING_KEY: &str = "input_polling\0";
+const PER_UPDATE_POLLING: &str = "every update";
+
+/// Whether the input polling option is set to poll before every logic update.
+static PER_UPDATE: AtomicBool = AtomicBool::new(false);
+/// Logic updates run since the content was loaded or the core reset.
+static TICK: AtomicU64 = AtomicU64::new(0);
+/// The logic update the frontend last polled the input hardware for.
+static INPUT_TICK: AtomicU64 = AtomicU64::new(0);
+
+/// Timing of the current logic update and of the input it sees, in logic
+/// updates and in microseconds of game time since the content was loaded or
+/// the core reset.
+#[derive(Clone, Copy, Debug)]
+pub struct TickTime
+{
+    pub tick: u64,
+    pub usec: u64,
+    pub input_tick: u64,
+    pub input_usec: u64,
+}
+
+/// Returns the timing of the current logic update. When input is polled once
+/// per video frame, input_tick is the first update of the frame, so later
+/// updates in the frame see older input.
+pub fn tick_time() -> TickTime
+{
+    let logic_rate = region::core_logic_rate();
+    let usec = |tick: u64| (tick as u128 * 1_000_000 / logic_rate as u128) as u64;
+    let tick = TICK.load(Ordering::Relaxed);
+    let input_tick = INPUT_TICK.load(Ordering::Relaxed);
+    TickTime { tick, usec: usec(tick), input_tick, input_usec: usec(input_tick) }
+}
+
+/// Builds the input polling option as null terminated key and value strings
+/// for RETRO_ENVIRONMENT_SET_VARIABLES.
+pub fn variable() -> (String, String)
+{
+    (INPUT_POLLING_KEY.to_string(),
+     format!("Input polling; every frame|{}\0", PER_UPDATE_POLLING))
+}
+
+/// Reads the input polling option. Called whenever the frontend reports a core
+/// option change.
+pub fn load_options()
+{
+    let per_update = get_variable(INPUT_POLLING_KEY).as_deref() == Some(PER_UPDATE_POLLING);
+    PER_UPDATE.store(per_update, Ordering::Relaxed);
+}
+
+/// Asks the frontend to poll the input hardware before logic update `index`
+/// of a video frame. The first update of every frame polls, the others only
+/// when polling every update, so frontends that read the hardware on every
+/// poll give the core finer grained input.
+pub fn poll_for_tick(index: u32)
+{
+    if index == 0 || PER_UPDATE.load(Ordering::Relaxed) {
+        unsafe { retro_input_poll_cb.unwrap()(); }
+        INPUT_TICK.store(TICK.load(Ordering::Relaxed), Ordering::Relaxed);
     }
 }
+
+/// Moves on to the next logic update.
+pub fn end_tick()
+{
+    TICK.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Restarts the tick count for new content or a reset core.
+pub fn reset_ticks()
+{
+    set_ticks(0, 0);
+}
+
+/// Restores the tick count from a save state.
+pub fn set_ticks(tick: u64, input_tick: u64)
+{
+    TICK.store(tick, Ordering::Relaxed);
+    INPUT_TICK.store(input_tick, Ordering::Relaxed);
+}
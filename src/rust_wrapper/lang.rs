@@ -0,0 +1,230 @@
+//! Translations of the core's text.
+//!
+//! The core lists a string table for each language it is translated to in
+//! STRING_TABLES, usually with include_str!, so the tables are built into
+//! the core. tr(key) looks the key up in the table for the current language,
+//! then in the English table, and otherwise returns the key itself, so
+//! English text can serve as its own key.
+//!
+//! The language is the one the frontend reports with
+//! RETRO_ENVIRONMENT_GET_LANGUAGE, unless the language core option, offered
+//! when there is more than one table, chooses another. ENV_VARS descriptions
+//! are translated too, into the frontend's language, as the options are set
+//! before the option can be read.
+//!
+//! A table is UTF-8 text with one "key = value" entry per line. Blank lines
+//! and lines starting with # are skipped, and \n in a value is a line break.
+
+use core::ffi::{c_int, c_void};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::libretro::*;
+use super::{environment, get_variable};
+
+const LANGUAGE_KEY: &str = "language\0";
+const AUTO_LANGUAGE: &str = "auto";
+
+/// The languages libretro frontends know about.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Language
+{
+    English = RETRO_LANGUAGE_ENGLISH as isize,
+    Japanese = RETRO_LANGUAGE_JAPANESE as isize,
+    French = RETRO_LANGUAGE_FRENCH as isize,
+    Spanish = RETRO_LANGUAGE_SPANISH as isize,
+    German = RETRO_LANGUAGE_GERMAN as isize,
+    Italian = RETRO_LANGUAGE_ITALIAN as isize,
+    Dutch = RETRO_LANGUAGE_DUTCH as isize,
+    Portuguese = RETRO_LANGUAGE_PORTUGUESE as isize,
+    Russian = RETRO_LANGUAGE_RUSSIAN as isize,
+    Korean = RETRO_LANGUAGE_KOREAN as isize,
+    ChineseTraditional = RETRO_LANGUAGE_CHINESE_TRADITIONAL as isize,
+    ChineseSimplified = RETRO_LANGUAGE_CHINESE_SIMPLIFIED as isize,
+}
+
+const LANGUAGES: [Language; RETRO_LANGUAGE_LAST as usize] = [
+    Language::English, Language::Japanese, Language::French, Language::Spanish,
+    Language::German, Language::Italian, Language::Dutch, Language::Portuguese,
+    Language::Russian, Language::Korean, Language::ChineseTraditional,
+    Language::ChineseSimplified,
+];
+
+impl Language
+{
+    /// The language's name for itself, as shown in the language option.
+    pub fn name(self) -> &'static str
+    {
+        match self {
+            Language::English => "English",
+            Language::Japanese => "日本語",
+            Language::French => "Français",
+            Language::Spanish => "Español",
+            Language::German => "Deutsch",
+            Language::Italian => "Italiano",
+            Language::Dutch => "Nederlands",
+            Language::Portuguese => "Português",
+            Language::Russian => "Русский",
+            Language::Korean => "한국어",
+            Language::ChineseTraditional => "繁體中文",
+            Language::ChineseSimplified => "简体中文",
+        }
+    }
+}
+
+static LANGUAGE: AtomicUsize = AtomicUsize::new(Language::English as usize);
+
+type Table = HashMap<&'static str, &'static str>;
+
+/// STRING_TABLES, parsed on first use.
+fn tables() -> &'static HashMap<Language, Table>
+{
+    use super::super::STRING_TABLES;
+    static TABLES: OnceLock<HashMap<Language, Table>> = OnceLock::new();
+
+    TABLES.get_or_init(|| {
+        STRING_TABLES.iter()
+            .map(|&(language, text)| (language, parse(language, text)))
+            .collect()
+    })
+}
+
+fn parse(language: Language, text: &'static str) -> Table
+{
+    let mut table = HashMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((key, value)) => {
+                let value = value.trim();
+                // Tables are parsed once, so the few values with line breaks
+                // can be kept for good
+                let value: &'static str = if value.contains("\\n") {
+                    Box::leak(value.replace("\\n", "\n").into_boxed_str())
+                } else {
+                    value
+                };
+                table.insert(key.trim(), value);
+            }
+            None => log_warn!("{:?} string table line {} has no =", language, number + 1),
+        }
+    }
+    table
+}
+
+/// The frontend's language, or English if it doesn't say.
+fn frontend_language() -> Language
+{
+    let mut language: c_int = RETRO_LANGUAGE_ENGLISH;
+    if !environment(RETRO_ENVIRONMENT_GET_LANGUAGE, &mut language as *mut c_int as *mut c_void) {
+        return Language::English;
+    }
+    LANGUAGES.get(language as usize).copied().unwrap_or(Language::English)
+}
+
+/// Languages with string tables, English first.
+fn translated_languages() -> Vec<Language>
+{
+    let tables = tables();
+    LANGUAGES.iter()
+        .copied()
+        .filter(|language| *language == Language::English || tables.contains_key(language))
+        .collect()
+}
+
+/// Picks up the frontend's language, before the options are set.
+pub fn init()
+{
+    LANGUAGE.store(frontend_language() as usize, Ordering::Relaxed);
+}
+
+/// Builds the language option as null terminated key and value strings for
+/// RETRO_ENVIRONMENT_SET_VARIABLES, if the core has more than one language.
+pub fn variable() -> Option<(String, String)>
+{
+    let languages = translated_languages();
+    if languages.len() < 2 {
+        return None;
+    }
+    let names: Vec<&str> = languages.iter().map(|language| language.name()).collect();
+    Some((LANGUAGE_KEY.to_string(),
+          format!("Language; {}|{}\0", AUTO_LANGUAGE, names.join("|"))))
+}
+
+/// Reads the language option. Called before content is loaded and whenever
+/// the frontend reports a core option change.
+pub fn load_options()
+{
+    let chosen = get_variable(LANGUAGE_KEY);
+    let language = LANGUAGES.iter()
+        .copied()
+        .find(|language| Some(language.name()) == chosen.as_deref())
+        .unwrap_or_else(frontend_language);
+    if LANGUAGE.swap(language as usize, Ordering::Relaxed) != language as usize {
+        log_info!("Language set to {:?}", language);
+    }
+}
+
+/// The language text is shown in.
+pub fn language() -> Language
+{
+    LANGUAGES[LANGUAGE.load(Ordering::Relaxed)]
+}
+
+/// Translates text into the current language. Returns the English text, or
+/// the key itself, when there's no translation.
+pub fn tr(key: &str) -> &str
+{
+    lookup(tables(), language(), key)
+}
+
+fn lookup<'a>(tables: &'a HashMap<Language, Table>, language: Language, key: &'a str) -> &'a str
+{
+    [language, Language::English].iter()
+        .find_map(|language| tables.get(language)?.get(key).copied())
+        .unwrap_or(key)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn parses_tables()
+    {
+        let table = parse(Language::French, "# Comment\n\
+                                             \n\
+                                             Game Over = Partie terminée\n\
+                                             \x20 Press Start=Appuyez sur Start  \n\
+                                             No equals sign\n\
+                                             Two lines = Ligne 1\\nLigne 2\n\
+                                             a = b = c\n\
+                                             Empty =\n");
+        assert_eq!(table.len(), 5);
+        assert_eq!(table["Game Over"], "Partie terminée");
+        assert_eq!(table["Press Start"], "Appuyez sur Start");
+        assert_eq!(table["Two lines"], "Ligne 1\nLigne 2");
+        assert_eq!(table["a"], "b = c");
+        assert_eq!(table["Empty"], "");
+        assert!(!table.contains_key("No equals sign") && !table.contains_key("# Comment"));
+    }
+
+    #[test]
+    fn falls_back_to_english_then_the_key()
+    {
+        let tables = HashMap::from([
+            (Language::English, parse(Language::English, "Lives = Lives left\nScore = Points")),
+            (Language::German, parse(Language::German, "Score = Punkte")),
+        ]);
+        assert_eq!(lookup(&tables, Language::German, "Score"), "Punkte");
+        assert_eq!(lookup(&tables, Language::German, "Lives"), "Lives left");
+        assert_eq!(lookup(&tables, Language::German, "Game Over"), "Game Over");
+        assert_eq!(lookup(&tables, Language::Japanese, "Score"), "Points");
+        assert_eq!(lookup(&HashMap::new(), Language::French, "Score"), "Score");
+    }
+}
@@ -20,12 +20,9 @@
  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
  */
 
-extern crate libc;
-use libc::types::common::c95::c_void;
-use libc::types::os::arch::c95::size_t;
-use libc::types::os::arch::c95::c_char;
-use libc::c_uint;
-use libc::c_int;
+use core::ffi::{c_char, c_double, c_float, c_int, c_uint, c_ulong, c_void};
+
+pub type size_t = usize;
 
 /* Used for checking API/ABI mismatches that can break libretro 
  * implementations.
@@ -48,12 +45,12 @@ pub const RETRO_API_VERSION:         c_uint = 1;
  */
 
 pub const RETRO_DEVICE_TYPE_SHIFT:         c_uint = 8;
-pub const RETRO_DEVICE_MASK:               c_uint = ((1 << RETRO_DEVICE_TYPE_SHIFT as uint) - 1);
-macro_rules! RETRO_DEVICE_SUBCLASS(
-   ($base:expr, $id:expr) => (
-      (((id + 1) << RETRO_DEVICE_TYPE_SHIFT) | base)
-   );
-)
+pub const RETRO_DEVICE_MASK:               c_uint = (1 << RETRO_DEVICE_TYPE_SHIFT) - 1;
+#[allow(non_snake_case)]
+pub const fn RETRO_DEVICE_SUBCLASS(base: c_uint, id: c_uint) -> c_uint
+{
+   ((id + 1) << RETRO_DEVICE_TYPE_SHIFT) | base
+}
 
 /* Input disabled. */
 pub const RETRO_DEVICE_NONE:         c_uint = 0;
@@ -644,7 +641,7 @@ pub const RETRO_ENVIRONMENT_GET_INPUT_DEVICE_CAPABILITIES: c_uint = 24;
                                             * Example bitmask: caps = (1 << RETRO_DEVICE_JOYPAD) | (1 << RETRO_DEVICE_ANALOG).
                                             * Should only be called in retro_run().
                                             */
-pub const RETRO_ENVIRONMENT_GET_SENSOR_INTERFACE: c_uint = (25 | RETRO_ENVIRONMENT_EXPERIMENTAL);
+pub const RETRO_ENVIRONMENT_GET_SENSOR_INTERFACE: c_uint = 25 | RETRO_ENVIRONMENT_EXPERIMENTAL;
                                            /* *mut retro_sensor_interface --
                                             * Gets access to the sensor interface.
                                             * The purpose of this interface is to allow
@@ -653,7 +650,7 @@ pub const RETRO_ENVIRONMENT_GET_SENSOR_INTERFACE: c_uint = (25 | RETRO_ENVIRONME
                                             * Reading sensor state is done via the normal 
                                             * input_state_callback API.
                                             */
-pub const RETRO_ENVIRONMENT_GET_CAMERA_INTERFACE: c_uint = (26 | RETRO_ENVIRONMENT_EXPERIMENTAL);
+pub const RETRO_ENVIRONMENT_GET_CAMERA_INTERFACE: c_uint = 26 | RETRO_ENVIRONMENT_EXPERIMENTAL;
                                            /* *mut retro_camera_callback --
                                             * Gets an interface to a video camera driver.
                                             * A libretro core can use this interface to get access to a 
@@ -813,7 +810,7 @@ pub const RETRO_ENVIRONMENT_SET_CONTROLLER_INFO: c_uint = 35;
                                             * Even if special device types are set in the libretro core, 
                                             * libretro should only poll input based on the base input device types.
                                             */
-pub const RETRO_ENVIRONMENT_SET_MEMORY_MAPS: c_uint = (36 | RETRO_ENVIRONMENT_EXPERIMENTAL);
+pub const RETRO_ENVIRONMENT_SET_MEMORY_MAPS: c_uint = 36 | RETRO_ENVIRONMENT_EXPERIMENTAL;
                                            /* *const retro_memory_map --
                                             * This environment call lets a libretro core tell the frontend 
                                             * about the memory maps this core emulates.
@@ -859,19 +856,27 @@ pub const RETRO_ENVIRONMENT_GET_LANGUAGE: c_uint = 39;
                                             * Returns the specified language of the frontend, if specified by the user.
                                             * It can be used by the core for localization purposes.
                                             */
+pub const RETRO_ENVIRONMENT_GET_TARGET_REFRESH_RATE: c_uint = 50 | RETRO_ENVIRONMENT_EXPERIMENTAL;
+                                           /* *mut c_float --
+                                            * Float value that lets us know what target refresh rate 
+                                            * is currently in use by the frontend.
+                                            *
+                                            * The core can use the returned value to set an ideal 
+                                            * refresh rate/framerate.
+                                            */
 
-pub const RETRO_MEMDESC_CONST:     c_uint = (1 << 0);   /* The frontend will never change this memory area once retro_load_game has returned. */
-pub const RETRO_MEMDESC_BIGENDIAN: c_uint = (1 << 1);   /* The memory area contains big endian data. Default is little endian. */
-pub const RETRO_MEMDESC_ALIGN_2:   c_uint = (1 << 16);  /* All memory access in this area is aligned to their own size, or 2, whichever is smaller. */
-pub const RETRO_MEMDESC_ALIGN_4:   c_uint = (2 << 16);
-pub const RETRO_MEMDESC_ALIGN_8:   c_uint = (3 << 16);
-pub const RETRO_MEMDESC_MINSIZE_2: c_uint = (1 << 24);  /* All memory in this region is accessed at least 2 bytes at the time. */
-pub const RETRO_MEMDESC_MINSIZE_4: c_uint = (2 << 24);
-pub const RETRO_MEMDESC_MINSIZE_8: c_uint = (3 << 24);
+pub const RETRO_MEMDESC_CONST:     c_uint = 1 << 0;   /* The frontend will never change this memory area once retro_load_game has returned. */
+pub const RETRO_MEMDESC_BIGENDIAN: c_uint = 1 << 1;   /* The memory area contains big endian data. Default is little endian. */
+pub const RETRO_MEMDESC_ALIGN_2:   c_uint = 1 << 16;  /* All memory access in this area is aligned to their own size, or 2, whichever is smaller. */
+pub const RETRO_MEMDESC_ALIGN_4:   c_uint = 2 << 16;
+pub const RETRO_MEMDESC_ALIGN_8:   c_uint = 3 << 16;
+pub const RETRO_MEMDESC_MINSIZE_2: c_uint = 1 << 24;  /* All memory in this region is accessed at least 2 bytes at the time. */
+pub const RETRO_MEMDESC_MINSIZE_4: c_uint = 2 << 24;
+pub const RETRO_MEMDESC_MINSIZE_8: c_uint = 3 << 24;
 #[repr(C)]
 pub struct retro_memory_descriptor
 {
-   flags: u64,
+   pub flags: u64,
 
    /* Pointer to the start of the relevant ROM or RAM chip.
     * It's strongly recommended to use 'offset' if possible, rather than 
@@ -1122,28 +1127,28 @@ pub type retro_log_printf_t = extern "C" fn(level: c_int,
 #[repr(C)]
 pub struct retro_log_callback
 {
-   pub log: retro_log_printf_t,
+   pub log: Option<retro_log_printf_t>,
 }
 
 /* Performance related functions */
 
 /* ID values for SIMD CPU features */
-pub const RETRO_SIMD_SSE:      c_uint = (1 << 0);
-pub const RETRO_SIMD_SSE2:     c_uint = (1 << 1);
-pub const RETRO_SIMD_VMX:      c_uint = (1 << 2);
-pub const RETRO_SIMD_VMX128:   c_uint = (1 << 3);
-pub const RETRO_SIMD_AVX:      c_uint = (1 << 4);
-pub const RETRO_SIMD_NEON:     c_uint = (1 << 5);
-pub const RETRO_SIMD_SSE3:     c_uint = (1 << 6);
-pub const RETRO_SIMD_SSSE3:    c_uint = (1 << 7);
-pub const RETRO_SIMD_MMX:      c_uint = (1 << 8);
-pub const RETRO_SIMD_MMXEXT:   c_uint = (1 << 9);
-pub const RETRO_SIMD_SSE4:     c_uint = (1 << 10);
-pub const RETRO_SIMD_SSE42:    c_uint = (1 << 11);
-pub const RETRO_SIMD_AVX2:     c_uint = (1 << 12);
-pub const RETRO_SIMD_VFPU:     c_uint = (1 << 13);
-pub const RETRO_SIMD_PS:       c_uint = (1 << 14);
-pub const RETRO_SIMD_AES:      c_uint = (1 << 15);
+pub const RETRO_SIMD_SSE:      c_uint = 1 << 0;
+pub const RETRO_SIMD_SSE2:     c_uint = 1 << 1;
+pub const RETRO_SIMD_VMX:      c_uint = 1 << 2;
+pub const RETRO_SIMD_VMX128:   c_uint = 1 << 3;
+pub const RETRO_SIMD_AVX:      c_uint = 1 << 4;
+pub const RETRO_SIMD_NEON:     c_uint = 1 << 5;
+pub const RETRO_SIMD_SSE3:     c_uint = 1 << 6;
+pub const RETRO_SIMD_SSSE3:    c_uint = 1 << 7;
+pub const RETRO_SIMD_MMX:      c_uint = 1 << 8;
+pub const RETRO_SIMD_MMXEXT:   c_uint = 1 << 9;
+pub const RETRO_SIMD_SSE4:     c_uint = 1 << 10;
+pub const RETRO_SIMD_SSE42:    c_uint = 1 << 11;
+pub const RETRO_SIMD_AVX2:     c_uint = 1 << 12;
+pub const RETRO_SIMD_VFPU:     c_uint = 1 << 13;
+pub const RETRO_SIMD_PS:       c_uint = 1 << 14;
+pub const RETRO_SIMD_AES:      c_uint = 1 << 15;
 
 #[allow(non_camel_case_types)]
 pub type retro_perf_tick_t = u64;
@@ -1268,7 +1273,7 @@ pub type retro_set_sensor_state_t = extern "C" fn(port: c_uint,
 #[allow(non_camel_case_types)]
 pub type retro_sensor_get_input_t = extern "C" fn(port: c_uint,
                                                    id: c_uint) 
-                                                   -> ::libc::c_float;
+                                                   -> c_float;
 #[allow(dead_code)]
 #[repr(C)]
 struct retro_sensor_interface
@@ -1329,7 +1334,7 @@ pub type retro_camera_frame_raw_framebuffer_t = extern "C" fn
 pub type retro_camera_frame_opengl_texture_t = extern "C" fn
                                               (texture_id: c_uint,
                                                texture_target: c_uint,
-                                               affine: *const ::libc::c_float);
+                                               affine: *const c_float);
 
 #[allow(dead_code)]
 #[repr(C)]
@@ -1338,7 +1343,7 @@ struct retro_camera_callback
    /* Set by libretro core. 
     * Example bitmask: caps = (1 << RETRO_CAMERA_BUFFER_OPENGL_TEXTURE) | (1 << RETRO_CAMERA_BUFFER_RAW_FRAMEBUFFER).
     */
-   pub caps: ::libc::c_ulong,
+   pub caps: c_ulong,
 
    pub width: c_uint, /* Desired resolution for camera. Is only used as a hint. */
    pub height: c_uint,
@@ -1391,10 +1396,10 @@ pub type retro_location_stop_t = extern "C" fn();
  * 0 if no new  location update has happened since the last time. */
 #[allow(non_camel_case_types)]
 pub type retro_location_get_position_t = extern "C" fn
-                                         (lat: *mut ::libc::c_double,
-                                          lon: *mut ::libc::c_double,
-                                          horiz_accuracy: *mut ::libc::c_double,
-                                          vert_accuracy: *mut ::libc::c_double) -> u8;
+                                         (lat: *mut c_double,
+                                          lon: *mut c_double,
+                                          horiz_accuracy: *mut c_double,
+                                          vert_accuracy: *mut c_double) -> u8;
 
 /* Callback which signals when the location driver is initialized 
  * and/or deinitialized.
@@ -1472,13 +1477,13 @@ struct retro_audio_callback
  *
  * In those scenarios the reference frame time value will be used. */
 #[allow(non_camel_case_types)]
-pub type retro_usec_t = ::libc::c_long;
+pub type retro_usec_t = i64;
 #[allow(non_camel_case_types)]
 pub type retro_frame_time_callback_t = extern "C" fn(usec: retro_usec_t);
 
 #[allow(dead_code)]
 #[repr(C)]
-struct retro_frame_time_callback
+pub struct retro_frame_time_callback
 {
    pub callback: retro_frame_time_callback_t,
    /* Represents the time of one frame. It is computed as 
@@ -1490,7 +1495,7 @@ struct retro_frame_time_callback
 /* Pass this to retro_video_refresh_t if rendering to hardware.
  * Passing NULL to retro_video_refresh_t is still a frame dupe as normal.
  * */
-pub const RETRO_HW_FRAME_BUFFER_VALID: *const c_void = -1i as *const c_void;
+pub const RETRO_HW_FRAME_BUFFER_VALID: *const c_void = usize::MAX as *const c_void;
 
 /* Invalidates the current HW context.
  * Any GL state is lost, and must not be deinitialized explicitly.
@@ -1509,7 +1514,7 @@ pub type retro_hw_context_reset_t = extern "C" fn();
  */
 #[allow(non_camel_case_types)]
 pub type retro_hw_get_current_framebuffer_t = extern "C" fn() -> 
-                                               ::libc::types::os::arch::c99::uintptr_t;
+                                               usize;
 
 /* Get a symbol from HW context. */
 #[allow(non_camel_case_types)]
@@ -1808,6 +1813,7 @@ pub struct retro_system_info
 }
 
 #[allow(dead_code)]
+#[derive(Default)]
 #[repr(C)]
 pub struct retro_game_geometry
 {
@@ -1824,6 +1830,7 @@ pub struct retro_game_geometry
 }
 
 #[allow(dead_code)]
+#[derive(Default)]
 #[repr(C)]
 pub struct retro_system_timing
 {
@@ -1832,6 +1839,7 @@ pub struct retro_system_timing
 }
 
 #[allow(dead_code)]
+#[derive(Default)]
 #[repr(C)]
 pub struct retro_system_av_info
 {
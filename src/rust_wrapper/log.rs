@@ -0,0 +1,373 @@
+//! Logging through the frontend's log interface.
+//!
+//! Messages logged before retro_set_environment() are queued until we know
+//! whether the frontend provides a log interface. Frontends without one get
+//! the messages on stderr instead. Nothing here may panic, as the panic hook
+//! logs through it.
+//!
+//! Cores should log with the log_debug!, log_info!, log_warn! and log_error!
+//! macros, which take format arguments like println!. Their messages are
+//! filtered by the automatic log level and log module core options, and each
+//! call site is rate limited so a message logged every frame cannot flood the
+//! log.
+
+use std::borrow::Cow;
+use std::ffi::CString;
+use std::fmt::{self, Write as FmtWrite};
+use std::io::Write;
+use std::mem;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+use super::libretro::*;
+use super::{get_variable, video_frame};
+
+/// Longest message passed on in one piece. Longer messages are truncated.
+pub const MAX_MESSAGE_LEN: usize = 1024;
+/// Backtraces get their own, larger limit.
+pub const MAX_BACKTRACE_LEN: usize = 16 * 1024;
+/// Messages kept while waiting for retro_set_environment().
+const MAX_QUEUED_MESSAGES: usize = 64;
+
+const TRUNCATED_MARKER: &str = "...";
+
+/// Messages each call site may log per RATE_LIMIT_FRAMES video frames.
+const RATE_LIMIT_BURST: u32 = 10;
+const RATE_LIMIT_FRAMES: u64 = 60;
+
+const LOG_LEVEL_KEY: &str = "log_level\0";
+const LOG_LEVEL_VALUES: &[(&str, LogLevel)] = &[("info", LogLevel::LogInfo),
+                                                ("debug", LogLevel::LogDebug),
+                                                ("warn", LogLevel::LogWarn),
+                                                ("error", LogLevel::LogError)];
+const LOG_MODULE_KEY: &str = "log_module\0";
+const ALL_MODULES: &str = "all";
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel
+{
+    LogDebug = RETRO_LOG_DEBUG as isize,
+    LogInfo = RETRO_LOG_INFO as isize,
+    LogWarn = RETRO_LOG_WARN as isize,
+    LogError = RETRO_LOG_ERROR as isize
+}
+
+impl LogLevel
+{
+    fn name(self) -> &'static str
+    {
+        match self {
+            LogLevel::LogDebug => "DEBUG",
+            LogLevel::LogInfo => "INFO",
+            LogLevel::LogWarn => "WARN",
+            LogLevel::LogError => "ERROR",
+        }
+    }
+}
+
+/// Lowest level the log macros pass on.
+static LEVEL_THRESHOLD: AtomicU8 = AtomicU8::new(LogLevel::LogInfo as u8);
+/// Index into LOG_MODULES of the module whose debug and info messages are
+/// shown, or usize::MAX for all modules.
+static MODULE_FILTER: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+enum Sink
+{
+    Pending,
+    Frontend(retro_log_printf_t),
+    Stderr,
+}
+
+struct LogState
+{
+    sink: Sink,
+    queue: Vec<(LogLevel, String)>,
+    dropped: usize,
+}
+
+static LOG: Mutex<LogState> = Mutex::new(LogState {
+    sink: Sink::Pending,
+    queue: Vec::new(),
+    dropped: 0,
+});
+
+fn lock() -> MutexGuard<'static, LogState>
+{
+    LOG.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Selects where messages go once the frontend has answered
+/// RETRO_ENVIRONMENT_GET_LOG_INTERFACE, and flushes any queued messages.
+pub fn set_log_interface(log: Option<retro_log_printf_t>)
+{
+    let mut state = lock();
+    state.sink = match log {
+        Some(cb) => Sink::Frontend(cb),
+        None => Sink::Stderr,
+    };
+    for (level, text) in mem::take(&mut state.queue) {
+        write(&state.sink, level, &text);
+    }
+    if state.dropped > 0 {
+        let text = format!("{} early log messages were dropped.", state.dropped);
+        write(&state.sink, LogLevel::LogWarn, &text);
+        state.dropped = 0;
+    }
+}
+
+/// Builds the automatic logging core options, as null terminated key and
+/// value strings for RETRO_ENVIRONMENT_SET_VARIABLES.
+pub fn variables() -> Vec<(String, String)>
+{
+    use super::super::LOG_MODULES;
+
+    let mut vars = Vec::with_capacity(2);
+    let levels: Vec<&str> = LOG_LEVEL_VALUES.iter().map(|&(name, _)| name).collect();
+    vars.push((LOG_LEVEL_KEY.to_string(),
+               format!("Log level; {}\0", levels.join("|"))));
+    if !LOG_MODULES.is_empty() {
+        vars.push((LOG_MODULE_KEY.to_string(),
+                   format!("Log module; {}|{}\0", ALL_MODULES, LOG_MODULES.join("|"))));
+    }
+    vars
+}
+
+/// Reads the logging core options. Called whenever the frontend reports a
+/// core option change.
+pub fn load_options()
+{
+    use super::super::LOG_MODULES;
+
+    let level = get_variable(LOG_LEVEL_KEY).and_then(|value| {
+        LOG_LEVEL_VALUES.iter().find(|&&(name, _)| name == value)
+    });
+    if let Some(&(_, level)) = level {
+        LEVEL_THRESHOLD.store(level as u8, Ordering::Relaxed);
+    }
+
+    let module = get_variable(LOG_MODULE_KEY)
+        .and_then(|value| LOG_MODULES.iter().position(|&m| m == value));
+    MODULE_FILTER.store(module.unwrap_or(usize::MAX), Ordering::Relaxed);
+}
+
+/// Whether a log macro in `module` should log at `level`. Warnings and errors
+/// are shown from every module, the module filter only hides debug and info
+/// messages.
+pub fn log_enabled(level: LogLevel, module: &str) -> bool
+{
+    use super::super::LOG_MODULES;
+
+    if (level as u8) < LEVEL_THRESHOLD.load(Ordering::Relaxed) {
+        return false;
+    }
+    if level >= LogLevel::LogWarn {
+        return true;
+    }
+    match LOG_MODULES.get(MODULE_FILTER.load(Ordering::Relaxed)) {
+        Some(filter) => module == *filter ||
+            (module.starts_with(filter) && module[filter.len()..].starts_with("::")),
+        None => true,
+    }
+}
+
+/// Per call site state for the log macros' rate limiting.
+pub struct RateLimit
+{
+    window: AtomicU64,
+    count: AtomicU32,
+    suppressed: AtomicU32,
+}
+
+impl RateLimit
+{
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> RateLimit
+    {
+        RateLimit { window: AtomicU64::new(0),
+                    count: AtomicU32::new(0),
+                    suppressed: AtomicU32::new(0) }
+    }
+
+    /// Returns None if the call site has used up its messages for the
+    /// current window, otherwise the number of messages suppressed since it
+    /// last logged.
+    pub fn check(&self) -> Option<u32>
+    {
+        let window = video_frame() / RATE_LIMIT_FRAMES;
+        if self.window.swap(window, Ordering::Relaxed) != window {
+            self.count.store(0, Ordering::Relaxed);
+        }
+        if self.count.fetch_add(1, Ordering::Relaxed) < RATE_LIMIT_BURST {
+            Some(self.suppressed.swap(0, Ordering::Relaxed))
+        }
+        else {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
+/// Formats and logs a message from one of the log macros.
+pub fn log_fmt(level: LogLevel, module: &str, suppressed: u32, args: fmt::Arguments)
+{
+    let mut text = String::new();
+    // Writing to a String only fails if a Display impl returns an error
+    if text.write_fmt(args).is_err() {
+        text.push_str("<formatting error>");
+    }
+    if suppressed > 0 {
+        let _ = write!(text, " ({} similar messages suppressed)", suppressed);
+    }
+    let module = module.split("::").last().unwrap_or(module);
+    retro_log(level, &format!("[{}] {}", module, text));
+}
+
+/// Logs a message, truncated to MAX_MESSAGE_LEN bytes. The text is passed to
+/// the frontend through a "%s" format, so '%' needs no escaping, and UTF-8 is
+/// passed on unchanged. Unlike the log macros this is never filtered or rate
+/// limited.
+pub fn retro_log(level: LogLevel, text: &str)
+{
+    log_truncated(level, text, MAX_MESSAGE_LEN);
+}
+
+pub fn retro_log_panic(msg: &str, file: &str, line: u32, backtrace: &str)
+{
+    // Keep room for the location, which matters more than the message tail
+    let msg_len = MAX_MESSAGE_LEN.saturating_sub(file.len() + 32).max(64);
+    retro_log(LogLevel::LogError, &format!("\"{}\" at {} line {}",
+                                           truncate(msg, msg_len), file, line));
+    log_truncated(LogLevel::LogError, &format!("Backtrace:\n{}", backtrace),
+                  MAX_BACKTRACE_LEN);
+}
+
+fn log_truncated(level: LogLevel, text: &str, max_len: usize)
+{
+    let text = truncate(text, max_len);
+    let mut state = lock();
+    match state.sink {
+        Sink::Pending => {
+            if state.queue.len() < MAX_QUEUED_MESSAGES {
+                state.queue.push((level, text.into_owned()));
+            }
+            else {
+                state.dropped += 1;
+            }
+        }
+        ref sink => write(sink, level, &text),
+    }
+}
+
+/// Cuts text down to at most max_len bytes on a char boundary, marking
+/// where it was cut. Only the marker is left if max_len is shorter than it.
+fn truncate(text: &str, max_len: usize) -> Cow<'_, str>
+{
+    if text.len() <= max_len {
+        return Cow::Borrowed(text);
+    }
+    let mut end = max_len.saturating_sub(TRUNCATED_MARKER.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    Cow::Owned(format!("{}{}", &text[..end], TRUNCATED_MARKER))
+}
+
+fn write(sink: &Sink, level: LogLevel, text: &str)
+{
+    match *sink {
+        Sink::Frontend(log) => {
+            let c_text = to_log_cstring(text);
+            log(level as i32, c"%s\n".as_ptr(), c_text.as_ptr());
+        }
+        // Errors writing to stderr have nowhere left to be reported
+        _ => { let _ = writeln!(std::io::stderr(), "[libretro {}] {}", level.name(), text); }
+    }
+}
+
+/// Converts log text to a C string, escaping embedded nulls so the message
+/// is not cut short.
+pub fn to_log_cstring(text: &str) -> CString
+{
+    let text = if text.contains('\0') {
+        Cow::Owned(text.replace('\0', "\\0"))
+    }
+    else {
+        Cow::Borrowed(text)
+    };
+    // No nulls remain, so this cannot fail
+    CString::new(text.into_owned()).unwrap_or_default()
+}
+
+#[allow(unused_macros)]
+macro_rules! log_at(
+    ($level:expr, $($arg:tt)+) => ({
+        static LIMIT: $crate::rust_wrapper::RateLimit =
+            $crate::rust_wrapper::RateLimit::new();
+        let level = $level;
+        if $crate::rust_wrapper::log_enabled(level, module_path!()) {
+            if let Some(suppressed) = LIMIT.check() {
+                $crate::rust_wrapper::log_fmt(level, module_path!(), suppressed,
+                                              format_args!($($arg)+));
+            }
+        }
+    });
+);
+
+#[allow(unused_macros)]
+macro_rules! log_debug(
+    ($($arg:tt)+) => (log_at!($crate::rust_wrapper::LogLevel::LogDebug, $($arg)+));
+);
+
+#[allow(unused_macros)]
+macro_rules! log_info(
+    ($($arg:tt)+) => (log_at!($crate::rust_wrapper::LogLevel::LogInfo, $($arg)+));
+);
+
+#[allow(unused_macros)]
+macro_rules! log_warn(
+    ($($arg:tt)+) => (log_at!($crate::rust_wrapper::LogLevel::LogWarn, $($arg)+));
+);
+
+#[allow(unused_macros)]
+macro_rules! log_error(
+    ($($arg:tt)+) => (log_at!($crate::rust_wrapper::LogLevel::LogError, $($arg)+));
+);
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn short_text_is_unchanged()
+    {
+        assert!(matches!(truncate("", 0), Cow::Borrowed("")));
+        assert!(matches!(truncate("hello", 5), Cow::Borrowed("hello")));
+        assert!(matches!(truncate("h\u{e9}llo", 6), Cow::Borrowed("h\u{e9}llo")));
+    }
+
+    #[test]
+    fn long_text_is_marked()
+    {
+        assert_eq!(truncate("hello world", 8), "hello...");
+        let long = "x".repeat(MAX_MESSAGE_LEN * 2);
+        let cut = truncate(&long, MAX_MESSAGE_LEN);
+        assert_eq!(cut.len(), MAX_MESSAGE_LEN);
+        assert!(cut.ends_with(TRUNCATED_MARKER));
+    }
+
+    #[test]
+    fn cuts_on_char_boundaries()
+    {
+        // The two byte \u{e9} is at bytes 4 and 5, across the cut at 5
+        assert_eq!(truncate("abcd\u{e9}fghij", 8), "abcd...");
+        assert_eq!(truncate("abcde\u{e9}ghij", 8), "abcde...");
+        // Four byte characters, the cut falling inside the first
+        assert_eq!(truncate("\u{1f600}\u{1f600}", 6), "...");
+        assert_eq!(truncate("\u{1f600}\u{1f600}", 7), "\u{1f600}...");
+        for max_len in 0..20 {
+            let cut = truncate("\u{e9}\u{1f600}a\u{e9}\u{1f600}b\u{e9}", max_len);
+            assert!(cut.len() <= max_len.max(TRUNCATED_MARKER.len()));
+        }
+    }
+}
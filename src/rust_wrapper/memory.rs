@@ -0,0 +1,564 @@
+//! Memory regions shared with the frontend.
+//!
+//! Cores register byte regions of their state, such as battery backed save
+//! RAM, by memory type in core_load_game(). The frontend reads them through
+//! retro_get_memory_data() and retro_get_memory_size(): it loads save RAM
+//! from disk into the region straight after content is loaded and writes it
+//! back out when the content is unloaded, and memory viewers inspect system
+//! RAM. Regions are 'static, so their addresses stay put for as long as the
+//! content is loaded, and they are forgotten when it is unloaded.
+//!
+//! Cores emulating a machine can also describe where its memory appears in
+//! the emulated address space with map_memory(). The mappings are checked and
+//! passed to the frontend with RETRO_ENVIRONMENT_SET_MEMORY_MAPS once
+//! core_load_game() returns, so achievements and cheat searches can use the
+//! machine's own addresses. Cheat codes use the same addresses.
+
+use core::ffi::{c_uint, c_void};
+use std::ffi::CString;
+use std::ptr;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use super::environment;
+use super::libretro::*;
+
+/// The memory types a frontend knows about.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MemoryId
+{
+    /// Battery backed RAM holding saved games, saved by the frontend.
+    SaveRam = RETRO_MEMORY_SAVE_RAM as isize,
+    /// The few bytes a real time clock keeps, saved by the frontend.
+    Rtc = RETRO_MEMORY_RTC as isize,
+    /// The main RAM of the emulated or virtual machine.
+    SystemRam = RETRO_MEMORY_SYSTEM_RAM as isize,
+    VideoRam = RETRO_MEMORY_VIDEO_RAM as isize,
+}
+
+const MEMORY_IDS: usize = 4;
+
+/// Where a mapping's bytes are.
+#[derive(Clone, Copy, Debug)]
+enum Source
+{
+    Registered(MemoryId),
+    /// Start address and length, as the raw pointer isn't Send.
+    Region(usize, usize),
+    Unmapped,
+}
+
+/// Where a region of memory appears in the emulated address space, built up
+/// like MemoryMapping::registered(MemoryId::SystemRam, 0x7e0000).len(0x20000)
+/// and passed to map_memory(). The fields follow retro_memory_descriptor in
+/// libretro.rs, which explains them in more detail.
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryMapping
+{
+    source: Source,
+    start: usize,
+    select: usize,
+    disconnect: usize,
+    len: Option<usize>,
+    offset: usize,
+    read_only: bool,
+    big_endian: bool,
+    align: usize,
+    min_access: usize,
+    address_space: &'static str,
+}
+
+impl MemoryMapping
+{
+    fn with_source(source: Source, start: usize) -> MemoryMapping
+    {
+        MemoryMapping {
+            source,
+            start,
+            select: 0,
+            disconnect: 0,
+            len: None,
+            offset: 0,
+            read_only: false,
+            big_endian: false,
+            align: 1,
+            min_access: 1,
+            address_space: "",
+        }
+    }
+
+    /// Maps a region passed to register_memory() at address start.
+    pub fn registered(id: MemoryId, start: usize) -> MemoryMapping
+    {
+        MemoryMapping::with_source(Source::Registered(id), start)
+    }
+
+    /// Maps any other static memory at address start, such as ROM. For
+    /// example MemoryMapping::new(ptr::addr_of_mut!(g_rom), 0x8000).
+    pub fn new(region: *mut [u8], start: usize) -> MemoryMapping
+    {
+        MemoryMapping::with_source(Source::Region(region as *mut u8 as usize, region.len()), start)
+    }
+
+    /// Claims addresses with nothing usable behind them, such as hardware
+    /// registers or open bus. Placed last with select set to all ones, it
+    /// also tells the frontend how large the address space is.
+    pub fn unmapped(start: usize) -> MemoryMapping
+    {
+        MemoryMapping::with_source(Source::Unmapped, start)
+    }
+
+    /// Address bits that must match start for the mapping to apply. Zero, the
+    /// default, maps each byte once, and needs a power of two length.
+    pub fn select(mut self, mask: usize) -> MemoryMapping
+    {
+        self.select = mask;
+        self
+    }
+
+    /// Address bits not connected to the memory, so the memory is mirrored.
+    pub fn disconnect(mut self, mask: usize) -> MemoryMapping
+    {
+        self.disconnect = mask;
+        self
+    }
+
+    /// Bytes mapped. Defaults to the rest of the region after the offset.
+    pub fn len(mut self, len: usize) -> MemoryMapping
+    {
+        self.len = Some(len);
+        self
+    }
+
+    /// Where in the region the mapping starts.
+    pub fn offset(mut self, offset: usize) -> MemoryMapping
+    {
+        self.offset = offset;
+        self
+    }
+
+    /// Promises the frontend the memory won't change once content is loaded.
+    pub fn read_only(mut self) -> MemoryMapping
+    {
+        self.read_only = true;
+        self
+    }
+
+    /// The memory holds big endian values, rather than little endian.
+    pub fn big_endian(mut self) -> MemoryMapping
+    {
+        self.big_endian = true;
+        self
+    }
+
+    /// Accesses are aligned to their size, up to this many bytes: 1, 2, 4
+    /// or 8.
+    pub fn align(mut self, bytes: usize) -> MemoryMapping
+    {
+        self.align = bytes;
+        self
+    }
+
+    /// Accesses read or write at least this many bytes: 1, 2, 4 or 8.
+    pub fn min_access(mut self, bytes: usize) -> MemoryMapping
+    {
+        self.min_access = bytes;
+        self
+    }
+
+    /// Names a separate address space, such as a sound CPU's, of up to 8
+    /// letters, digits, _ or -. Defaults to the main, unnamed one.
+    pub fn address_space(mut self, name: &'static str) -> MemoryMapping
+    {
+        self.address_space = name;
+        self
+    }
+
+    /// Checks the mapping against the libretro rules and builds its
+    /// descriptor, giving the reason it's invalid if it is.
+    fn descriptor(&self, regions: &[Option<(usize, usize)>]) -> Result<retro_memory_descriptor, String>
+    {
+        let (data, size) = match self.source {
+            Source::Registered(id) => regions[id as usize]
+                .ok_or_else(|| format!("{:?} memory isn't registered", id))?,
+            Source::Region(data, size) => (data, size),
+            Source::Unmapped => (0, 0),
+        };
+        let len = self.len.unwrap_or(size.saturating_sub(self.offset));
+        if data != 0 && len == 0 {
+            return Err("no bytes mapped".to_string());
+        }
+        if data != 0 && self.offset.checked_add(len).is_none_or(|end| end > size) {
+            return Err(format!("{} bytes from offset {} run past the end of the {} byte region",
+                               len, self.offset, size));
+        }
+        if self.start & !self.select != 0 && self.select != 0 {
+            return Err(format!("start {:#x} has bits outside select {:#x}", self.start, self.select));
+        }
+        if self.select == 0 && !len.is_power_of_two() {
+            return Err(format!("length {:#x} must be a power of two without select", len));
+        }
+        if self.select == 0 && self.start & insert_bits(len - 1, self.disconnect) != 0 {
+            return Err(format!("start {:#x} must be aligned to the length without select", self.start));
+        }
+
+        let mut flags = 0;
+        if self.read_only {
+            flags |= RETRO_MEMDESC_CONST;
+        }
+        if self.big_endian {
+            flags |= RETRO_MEMDESC_BIGENDIAN;
+        }
+        flags |= match self.align {
+            1 => 0,
+            2 => RETRO_MEMDESC_ALIGN_2,
+            4 => RETRO_MEMDESC_ALIGN_4,
+            8 => RETRO_MEMDESC_ALIGN_8,
+            bytes => return Err(format!("alignment of {} bytes isn't 1, 2, 4 or 8", bytes)),
+        };
+        flags |= match self.min_access {
+            1 => 0,
+            2 => RETRO_MEMDESC_MINSIZE_2,
+            4 => RETRO_MEMDESC_MINSIZE_4,
+            8 => RETRO_MEMDESC_MINSIZE_8,
+            bytes => return Err(format!("minimum access of {} bytes isn't 1, 2, 4 or 8", bytes)),
+        };
+        if data == 0 && flags != 0 {
+            return Err("unmapped memory can't have flags".to_string());
+        }
+
+        Ok(retro_memory_descriptor {
+            flags: flags as u64,
+            ptr: data as *mut c_void,
+            offset: self.offset,
+            start: self.start,
+            select: self.select,
+            disconnect: self.disconnect,
+            len,
+            addrspace: ptr::null(),
+        })
+    }
+}
+
+/// A mapping that passed the checks, for finding addresses in the map.
+struct Mapped
+{
+    data: usize,
+    offset: usize,
+    start: usize,
+    select: usize,
+    disconnect: usize,
+    len: usize,
+    big_endian: bool,
+}
+
+impl Mapped
+{
+    /// Follows the libretro rules for where an address lands in the mapped
+    /// memory: subtract start, pick off the disconnected bits, apply the
+    /// length and add the offset. A zero select is worked out from len and
+    /// disconnect, as frontends do.
+    fn find(&self, address: usize) -> Option<usize>
+    {
+        let select = if self.select == 0 {
+            !insert_bits(fill_bits_below(self.len.wrapping_sub(1)), self.disconnect)
+        } else {
+            self.select
+        };
+        if (address ^ self.start) & select != 0 {
+            return None;
+        }
+        let mut address = remove_bits(address.wrapping_sub(self.start), self.disconnect);
+        if self.len != 0 {
+            while address >= self.len {
+                address &= !(1 << address.ilog2());
+            }
+        }
+        Some(self.offset + address)
+    }
+}
+
+/// Squeezes the bits of mask out of address, shifting the higher bits down.
+fn remove_bits(address: usize, mask: usize) -> usize
+{
+    let mut address = address;
+    let mut mask = mask;
+    while mask != 0 {
+        let below = (mask - 1) & !mask;
+        address = address & below | (address >> 1) & !below;
+        mask = (mask & (mask - 1)) >> 1;
+    }
+    address
+}
+
+/// The reverse of remove_bits(): spreads address out, leaving zeros at the
+/// bits of mask.
+fn insert_bits(address: usize, mask: usize) -> usize
+{
+    let mut address = address;
+    let mut mask = mask;
+    while mask != 0 {
+        let below = (mask - 1) & !mask;
+        address = (address & !below) << 1 | address & below;
+        mask &= mask - 1;
+    }
+    address
+}
+
+/// Sets every bit below the highest set bit.
+fn fill_bits_below(value: usize) -> usize
+{
+    if value == 0 { 0 } else { usize::MAX >> value.leading_zeros() }
+}
+
+struct Memory
+{
+    regions: [Option<(usize, usize)>; MEMORY_IDS],
+    mappings: Vec<MemoryMapping>,
+    /// Mappings of the main address space passed to the frontend.
+    mapped: Vec<Mapped>,
+    /// Address space names of the submitted map, which the frontend may keep
+    /// pointers to.
+    address_spaces: Vec<CString>,
+}
+
+static MEMORY: Mutex<Memory> = Mutex::new(Memory {
+    regions: [None; MEMORY_IDS],
+    mappings: Vec::new(),
+    mapped: Vec::new(),
+    address_spaces: Vec::new(),
+});
+
+fn lock() -> MutexGuard<'static, Memory>
+{
+    MEMORY.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Shares a region of core state with the frontend until the content is
+/// unloaded. Call from core_load_game(). For example
+/// register_memory(MemoryId::SaveRam, unsafe { &mut *ptr::addr_of_mut!(g_sram) })
+/// for a static mut g_sram: [u8; 8192].
+pub fn register_memory(id: MemoryId, region: &'static mut [u8])
+{
+    let previous = lock().regions[id as usize].replace((region.as_mut_ptr() as usize, region.len()));
+    if previous.is_some() {
+        log_warn!("{:?} memory registered twice, keeping the second region", id);
+    }
+    log_debug!("{:?} memory registered, {} bytes", id, region.len());
+}
+
+/// Adds a mapping to the memory map passed to the frontend. Call from
+/// core_load_game(). Where mappings overlap, the first one added applies.
+pub fn map_memory(mapping: MemoryMapping)
+{
+    lock().mappings.push(mapping);
+}
+
+/// Forgets every region and mapping, when content is loaded or unloaded.
+pub fn clear()
+{
+    let mut memory = lock();
+    memory.regions = [None; MEMORY_IDS];
+    memory.mappings.clear();
+    memory.mapped.clear();
+}
+
+/// Checks the mappings added by the core and passes the valid ones to the
+/// frontend. Invalid mappings are logged and left out.
+pub fn submit_map()
+{
+    let mut memory = lock();
+    if memory.mappings.is_empty() {
+        return;
+    }
+
+    let names: Vec<&str> = memory.mappings.iter().map(|mapping| mapping.address_space).collect();
+    let mut descriptors = Vec::new();
+    let mut mapped = Vec::new();
+    let mut address_spaces = Vec::new();
+    for (index, mapping) in memory.mappings.iter().enumerate() {
+        let descriptor = check_address_space(mapping.address_space, &names)
+            .and_then(|()| mapping.descriptor(&memory.regions));
+        match descriptor {
+            Ok(mut descriptor) => {
+                if mapping.address_space.is_empty() {
+                    mapped.push(Mapped {
+                        data: descriptor.ptr as usize,
+                        offset: descriptor.offset,
+                        start: descriptor.start,
+                        select: descriptor.select,
+                        disconnect: descriptor.disconnect,
+                        len: descriptor.len,
+                        big_endian: mapping.big_endian,
+                    });
+                } else {
+                    let name = CString::new(mapping.address_space).unwrap();
+                    descriptor.addrspace = name.as_ptr();
+                    address_spaces.push(name);
+                }
+                descriptors.push(descriptor);
+            }
+            Err(reason) => log_error!("Memory mapping {} left out: {}", index, reason),
+        }
+    }
+    // Moving the names keeps their heap buffers, so the pointers stay valid
+    memory.address_spaces = address_spaces;
+    memory.mapped = mapped;
+    if descriptors.is_empty() {
+        log_warn!("No valid memory mappings, so no memory map passed to the frontend");
+        return;
+    }
+
+    let mut map = retro_memory_map {
+        descriptors: descriptors.as_ptr(),
+        num_descriptors: descriptors.len() as c_uint,
+    };
+    if environment(RETRO_ENVIRONMENT_SET_MEMORY_MAPS, &mut map as *mut _ as *mut c_void) {
+        log_debug!("Memory map of {} mappings passed to the frontend", descriptors.len());
+    } else {
+        log_info!("Frontend doesn't support memory maps");
+    }
+}
+
+/// Address space names must be short and plain, and mustn't read as another
+/// address space's name followed by a hexadecimal address.
+fn check_address_space(name: &str, names: &[&str]) -> Result<(), String>
+{
+    if name.len() > 8 || !name.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-') {
+        return Err(format!("address space name \"{}\" isn't up to 8 letters, digits, _ or -", name));
+    }
+    let ambiguous = names.iter().find(|&&other| {
+        name.len() > other.len() && name.starts_with(other) &&
+            name[other.len()..].bytes().all(|c| c.is_ascii_digit() || (b'A'..=b'F').contains(&c))
+    });
+    match ambiguous {
+        Some(other) => Err(format!("address space \"{}\" reads as \"{}\" and an address", name, other)),
+        None => Ok(()),
+    }
+}
+
+/// Finds the byte at an address of the main address space of the memory map,
+/// or at an offset into system RAM if the core has no memory map, and whether
+/// values there are big endian. Unmapped addresses and open bus give None.
+pub fn resolve(address: usize) -> Option<(*mut u8, bool)>
+{
+    let memory = lock();
+    if memory.mappings.is_empty() {
+        let (data, size) = memory.regions[MemoryId::SystemRam as usize]?;
+        return (address < size).then(|| ((data + address) as *mut u8, false));
+    }
+    let (mapped, offset) = memory.mapped.iter()
+        .find_map(|mapped| mapped.find(address).map(|offset| (mapped, offset)))?;
+    (mapped.data != 0).then(|| ((mapped.data + offset) as *mut u8, mapped.big_endian))
+}
+
+fn lookup(id: c_uint) -> Option<(usize, usize)>
+{
+    lock().regions.get((id & RETRO_MEMORY_MASK) as usize).copied().flatten()
+}
+
+/// retro_get_memory_data() for a libretro memory id.
+pub fn data(id: c_uint) -> *mut c_void
+{
+    lookup(id).map_or(ptr::null_mut(), |(data, _)| data as *mut c_void)
+}
+
+/// retro_get_memory_size() for a libretro memory id.
+pub fn size(id: c_uint) -> usize
+{
+    lookup(id).map_or(0, |(_, len)| len)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn mapped(start: usize, select: usize, disconnect: usize, len: usize, offset: usize) -> Mapped
+    {
+        Mapped { data: 1, offset, start, select, disconnect, len, big_endian: false }
+    }
+
+    #[test]
+    fn bits_are_removed_and_inserted()
+    {
+        assert_eq!(remove_bits(0b1011_0110, 0b0010_0100), 0b10_1010);
+        assert_eq!(remove_bits(0x12345, 0), 0x12345);
+        assert_eq!(remove_bits(0xffff, !0x1fff), 0x1fff);
+        assert_eq!(insert_bits(0b10_1010, 0b0010_0100), 0b1001_0010);
+        for mask in [0, 1, 0x8000, 0x408000, 0xff00_0000] {
+            for address in [0, 1, 0x1234, 0x7fff, 0xabcdef] {
+                assert_eq!(remove_bits(insert_bits(address, mask), mask), address);
+            }
+        }
+        assert_eq!(fill_bits_below(0), 0);
+        assert_eq!(fill_bits_below(0x7ff), 0x7ff);
+        assert_eq!(fill_bits_below(0x401), 0x7ff);
+    }
+
+    #[test]
+    fn plain_range()
+    {
+        let ram = mapped(0x2000, 0, 0, 0x1000, 0x100);
+        assert_eq!(ram.find(0x2000), Some(0x100));
+        assert_eq!(ram.find(0x2fff), Some(0x10ff));
+        assert_eq!(ram.find(0x1fff), None);
+        assert_eq!(ram.find(0x3000), None);
+        assert_eq!(ram.find(0x12000), None);
+    }
+
+    #[test]
+    fn mirrored_region()
+    {
+        // 2KB of RAM repeated four times over the first 8KB
+        let ram = mapped(0, 0xe000, 0, 0x800, 0);
+        assert_eq!(ram.find(0x0123), Some(0x123));
+        assert_eq!(ram.find(0x0923), Some(0x123));
+        assert_eq!(ram.find(0x1923), Some(0x123));
+        assert_eq!(ram.find(0x1fff), Some(0x7ff));
+        assert_eq!(ram.find(0x2000), None);
+
+        // libretro.h's SNES work RAM, mirrored into every low bank
+        let wram = mapped(0, 0x40e000, !0x1fff, 0, 0);
+        assert_eq!(wram.find(0x001234), Some(0x1234));
+        assert_eq!(wram.find(0x3f1234), Some(0x1234));
+        assert_eq!(wram.find(0x002000), None);
+        assert_eq!(wram.find(0x401234), None);
+    }
+
+    #[test]
+    fn disconnected_bits()
+    {
+        // libretro.h's SNES LoROM: 32KB of ROM in the top half of each bank
+        let rom = mapped(0x008000, 0x408000, 0x8000, 512 * 1024, 0);
+        assert_eq!(rom.find(0x008000), Some(0));
+        assert_eq!(rom.find(0x00ffff), Some(0x7fff));
+        assert_eq!(rom.find(0x018000), Some(0x8000));
+        assert_eq!(rom.find(0x0f8000), Some(0x78000));
+        // Past the end of the ROM, high bits are cleared until it fits
+        assert_eq!(rom.find(0x108000), Some(0));
+        assert_eq!(rom.find(0x3fffff), Some(0x7ffff));
+        assert_eq!(rom.find(0x800000 | 0x018000), Some(0x8000));
+        assert_eq!(rom.find(0x000000), None);
+        assert_eq!(rom.find(0x408000), None);
+
+        // Without a select, the region spans len plus the disconnected bits,
+        // and only addresses with those bits clear are mapped
+        let io = mapped(0x1000, 0, 0x10, 0x100, 0);
+        assert_eq!(io.find(0x1025), Some(0x15));
+        assert_eq!(io.find(0x1105), Some(0x85));
+        assert_eq!(io.find(0x11ef), Some(0xff));
+        assert_eq!(io.find(0x1015), None);
+        assert_eq!(io.find(0x1200), None);
+    }
+
+    #[test]
+    fn unmapped_addresses()
+    {
+        let high = mapped(0x8000, 0x8000, 0, 0x8000, 0);
+        assert_eq!(high.find(0x7fff), None);
+        assert_eq!(high.find(0x8000), Some(0));
+        let nothing = mapped(0xff0000, 0xff0000, 0, 0x10000, 0);
+        assert_eq!(nothing.find(0xfe0000), None);
+        assert_eq!(nothing.find(0xff1234), Some(0x1234));
+    }
+}
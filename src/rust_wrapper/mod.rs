@@ -1,47 +1,101 @@
-extern crate core;
-extern crate libc;
-extern crate collections;
-
-use libc::c_uint;
-use libc::size_t;
-use libc::types::common::c95::c_void;
-use libc::types::os::arch::c95::c_char;
-use core::prelude::*;
-use core::intrinsics::transmute;
-use core::atomic::{AtomicBool, SeqCst, INIT_ATOMIC_BOOL};
-use collections::*;
-
-use rust_wrapper::libretro::*;
-pub use rust_wrapper::input::{InputState, ButtonState, ControllerButton,
-                              PadB, PadY, PadSelect, PadStart, PadUp, PadDown,
-                              PadLeft, PadRight, PadA, PadX, PadL, PadR,
-                              PadL2, PadR2, PadL3, PadR3};
+#![allow(non_upper_case_globals)]
+// The unsafe entry points are only called by the frontend, under the rules of
+// the libretro API.
+#![allow(clippy::missing_safety_doc)]
+
+use core::ffi::{c_char, c_uint, c_void};
+use std::ffi::CStr;
+use std::ptr;
+use std::slice;
+use std::sync::{Condvar, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+
+use self::libretro::*;
+use self::panic::catch_panic;
+pub use self::input::{InputState, ButtonState, ControllerButton, TickTime, tick_time};
+pub use self::input::ControllerButton::{PadB, PadY, PadSelect, PadStart, PadUp,
+                                        PadDown, PadLeft, PadRight, PadA, PadX,
+                                        PadL, PadR, PadL2, PadR2, PadL3, PadR3};
+pub use self::log::{retro_log, LogLevel};
+pub use self::notify::notify;
+pub use self::serialize::{StateReader, StateWriter};
+pub use self::fixed::{Angle, Fixed, Q16_16, Q24_8, Vec2, Vec3};
+pub use self::paths::{paths, read_save_file, read_system_file, save_file, system_file,
+                      write_save_file, Paths};
+pub use self::region::{region, set_region, Region, RegionSource};
+pub use self::rng::{rng, seed_rng, CoreRng, Rng};
+pub use self::cheat::{cheats_active, CoreCheat};
+pub use self::disk::{inserted_disk, set_disk_control, DiskControl, DiskList};
+pub use self::subsystem::{Content, Subsystem, SubsystemContent};
+pub use self::memory::{map_memory, register_memory, MemoryId, MemoryMapping};
+pub use self::lang::{language, tr, Language};
+pub use self::text::{draw_text, text_size, BitmapFont};
+pub use self::user::{sanitize_name, username, MAX_NAME_CHARS};
+pub use self::score::{HighScore, HighScores};
+pub use self::perf::{cpu_features, PerfCounter, PerfScope};
+#[doc(hidden)] pub use self::log::{log_enabled, log_fmt, RateLimit};
+#[macro_use] mod log;
+#[macro_use] mod perf;
+mod cheat;
+mod check;
+mod disk;
+mod fixed;
+mod frame_rate;
 mod input;
-mod lang_items;
-#[allow(dead_code)] mod libretro;
-#[allow(dead_code)] #[path = "rustrt_files/mutex.rs"] mod mutex;
-#[allow(dead_code)] #[path = "rustrt_files/thread.rs"] mod thread;
-#[allow(dead_code)] #[path = "rustrt_files/stack.rs"] mod stack;
-#[allow(dead_code)] #[path = "rustrt_files/stack_overflow.rs"] mod stack_overflow;
+mod lang;
+mod memory;
+mod movie;
+mod notify;
+mod panic;
+mod paths;
+mod region;
+mod rewind;
+mod rng;
+mod score;
+mod serialize;
+mod subsystem;
+mod text;
+mod user;
+#[allow(dead_code, non_camel_case_types, clippy::all)] pub mod libretro;
 
 macro_rules! CORE_NAME(
     ($name:expr) => (
         static CORE_NAME: &'static str = concat!($name,"\0");
         );
-    )
+    );
 
 macro_rules! CORE_VERSION(
     ($version:expr) => (
         static CORE_VERSION: &'static str = concat!($version,"\0");
         );
-    )
+    );
 
 macro_rules! VALID_EXTENSIONS(
     ($ext:expr) => (
         static VALID_EXTENSIONS: &'static str = concat!($ext,"\0");
         );
-    )
-    
+    );
+
+/// The content passed to core_load_game().
+pub struct GameContent<'a>
+{
+    /// Path of the content, if the frontend has one.
+    pub path: Option<String>,
+    /// The content itself, empty when there is none. Only valid during
+    /// core_load_game().
+    pub data: &'a [u8],
+}
+
+impl GameContent<'_>
+{
+    /// Was content loaded, rather than the core started without any?
+    pub fn is_present(&self) -> bool
+    {
+        self.path.is_some() || !self.data.is_empty()
+    }
+}
+
 pub struct EnvVar {
     pub key: &'static str,
     pub desc: &'static str,
@@ -55,14 +109,14 @@ pub static mut retro_video_refresh_cb: Option<retro_video_refresh_t> = None;
 #[no_mangle]
 pub unsafe extern "C" fn retro_set_video_refresh(cb: retro_video_refresh_t)
 {
-    retro_video_refresh_cb = Some(cb);
+    catch_panic("retro_set_video_refresh", (), || retro_video_refresh_cb = Some(cb));
 }
 
 pub static mut retro_audio_sample_cb: Option<retro_audio_sample_t> = None;
 #[no_mangle]
 pub unsafe extern "C" fn retro_set_audio_sample(cb: retro_audio_sample_t)
 {
-    retro_audio_sample_cb = Some(cb);
+    catch_panic("retro_set_audio_sample", (), || retro_audio_sample_cb = Some(cb));
 }
 
 pub static mut retro_audio_sample_batch_cb: Option<retro_audio_sample_batch_t>
@@ -70,113 +124,127 @@ pub static mut retro_audio_sample_batch_cb: Option<retro_audio_sample_batch_t>
 #[no_mangle]
 pub unsafe extern "C" fn retro_set_audio_sample_batch(cb: retro_audio_sample_batch_t)
 {
-    retro_audio_sample_batch_cb = Some(cb);
+    catch_panic("retro_set_audio_sample_batch", (), || retro_audio_sample_batch_cb = Some(cb));
 }
 
 pub static mut retro_input_poll_cb: Option<retro_input_poll_t> = None;
 #[no_mangle]
 pub unsafe extern "C" fn retro_set_input_poll(cb: retro_input_poll_t)
 {
-    retro_input_poll_cb = Some(cb);
+    catch_panic("retro_set_input_poll", (), || retro_input_poll_cb = Some(cb));
 }
 
 pub static mut retro_input_state_cb: Option<retro_input_state_t> = None;
 #[no_mangle]
 pub unsafe extern "C" fn retro_set_input_state(cb: retro_input_state_t)
 {
-    retro_input_state_cb = Some(cb);
+    catch_panic("retro_set_input_state", (), || retro_input_state_cb = Some(cb));
 }
 
 static NO_CONTENT_FLAG: u8  = true as u8;
 static REQUIRED_CONTENT_FLAG: u8 = false as u8;
 
-pub enum CoreLogicRate {
-    LogicRate60 = 60,
-    LogicRate120 = 120,
-    LogicRate720 = 720,
-}
+static mut retro_environment_cb: Option<retro_environment_t> = None;
 
-static FRAME_RATE_KEY: &'static str = "frame_rate\0";
-static LOW_FRAME_RATE_VALUES: &'static str =
-    "Frame rate; 60|30\0";
-static MEDIUM_FRAME_RATE_VALUES: &'static str =
-    "Frame rate; 60|120|30\0";
-static HIGH_FRAME_RATE_VALUES: &'static str =
-    "Frame rate; 60|72|80|90|102.9|120|144|180|240|24|30|48|51.4|\0";
+/// Calls the frontend environment callback, returning false if the frontend
+/// has not provided one yet or does not support the command.
+fn environment(cmd: c_uint, data: *mut c_void) -> bool
+{
+    match unsafe { retro_environment_cb } {
+        Some(cb) => cb(cmd, data) != 0,
+        None => false,
+    }
+}
 
-static mut retro_environment_cb: Option<retro_environment_t> = None;
-static mut retro_log_cb: Option<retro_log_printf_t> = None;
 #[no_mangle]
 pub extern "C" fn retro_set_environment(cb: retro_environment_t)
 {
-    use super::{NO_CONTENT, ENV_VARS, CORE_LOGIC_RATE};
-    use collections::slice::{OrdSliceAllocPrelude};
+    catch_panic("retro_set_environment", (), || set_environment(cb));
+}
 
-    unsafe {
-        retro_environment_cb = Some(cb);
-
-        let log_interface = retro_log_callback { log: transmute(0u) };
-        retro_environment_cb.unwrap()(RETRO_ENVIRONMENT_GET_LOG_INTERFACE,
-                                      transmute(&log_interface));
-        retro_log_cb = Some(log_interface.log);
-    
-        let no_content: *mut c_void =
-            if NO_CONTENT {
-                transmute(&NO_CONTENT_FLAG)
-            } else {
-                transmute(&REQUIRED_CONTENT_FLAG)
-            };
-        retro_environment_cb.unwrap()(RETRO_ENVIRONMENT_SET_SUPPORT_NO_GAME,
-                                      no_content);
+fn set_environment(cb: retro_environment_t)
+{
+    use super::{NO_CONTENT, ENV_VARS};
+
+    unsafe { retro_environment_cb = Some(cb); }
+
+    // Frontends without a log interface get our messages on stderr
+    let mut log_interface = retro_log_callback { log: None };
+    if environment(RETRO_ENVIRONMENT_GET_LOG_INTERFACE,
+                   &mut log_interface as *mut _ as *mut c_void) {
+        log::set_log_interface(log_interface.log);
     }
+    else {
+        log::set_log_interface(None);
+    }
+    lang::init();
+    perf::init();
+
+    let no_content =
+        if NO_CONTENT {
+            &NO_CONTENT_FLAG
+        } else {
+            &REQUIRED_CONTENT_FLAG
+        };
+    environment(RETRO_ENVIRONMENT_SET_SUPPORT_NO_GAME,
+                no_content as *const u8 as *mut c_void);
 
     // reserve space for automatically implemented + null retro_variables
-    let num_vars = ENV_VARS.len() + 2;
+    let num_vars = ENV_VARS.len() + 16;
     let mut retro_variables = Vec::<retro_variable>::with_capacity(num_vars);
     // Rust needs to hold onto the & references until after the call to C
     let mut keystrings = Vec::<String>::with_capacity(num_vars);
     let mut descstrings = Vec::<String>::with_capacity(num_vars);
-    
+
     // add the automatic env variables
-    retro_variables.push(
-        retro_variable { key: FRAME_RATE_KEY.as_ptr() as *const i8,
-                         value: match CORE_LOGIC_RATE {
-                             LogicRate60 => LOW_FRAME_RATE_VALUES,
-                             LogicRate120 => MEDIUM_FRAME_RATE_VALUES,
-                             LogicRate720 => HIGH_FRAME_RATE_VALUES,
-                         }.as_ptr() as *const c_char } );
-    keystrings.push(String::from_str(FRAME_RATE_KEY));
+    let mut auto_vars = frame_rate::variables();
+    auto_vars.push(input::variable());
+    auto_vars.push(movie::variable());
+    auto_vars.extend(rewind::variables());
+    auto_vars.push(check::variable());
+    auto_vars.push(cheat::variable());
+    auto_vars.extend(region::variable());
+    auto_vars.extend(lang::variable());
+    auto_vars.push(perf::variable());
+    auto_vars.extend(log::variables());
+    for (key, value) in auto_vars {
+        retro_variables.push(
+            retro_variable { key: key.as_ptr() as *const c_char,
+                             value: value.as_ptr() as *const c_char } );
+        keystrings.push(key);
+        descstrings.push(value);
+    }
 
     // add the use env variables
     for var in ENV_VARS.iter() {
-        for byte in var.key.as_bytes().iter() {
-            if *byte == 0u8 { panic!("ENV_VAR key must not contain nulls."); }
+        if var.key.as_bytes().contains(&0u8) {
+            panic!("ENV_VAR key must not contain nulls.");
         }
         let key = var.key.to_ascii_cstring();
 
-        for byte in var.desc.as_bytes().iter() {
-            if *byte == 0u8 { panic!("ENV_VAR desc must not contain nulls."); }
+        if var.desc.as_bytes().contains(&0u8) {
+            panic!("ENV_VAR desc must not contain nulls.");
         }
+        let desc = tr(var.desc);
         // desc.len() + semicolon + space + [value.len() + pipe]
         let value_max_len =
-            var.desc.len() + 1 + 1 +
+            desc.len() + 1 + 1 +
             var.values.iter().fold(0, |sum, &x| sum + x.len() + 1);
         let mut value_string = String::with_capacity(value_max_len);
 
-        value_string.push_str(var.desc);
+        value_string.push_str(desc);
         value_string.push_str("; ");
 
         for value in var.values.iter() {
-            for byte in value.as_bytes().iter() {
-                if *byte == 0u8 {
-                    panic!("ENV_VAR values must not contain nulls.");
-                }
+            if value.as_bytes().contains(&0u8) {
+                panic!("ENV_VAR values must not contain nulls.");
             }
-            value_string.push_str(*value);
-            value_string.push_str("|");
+            value_string.push_str(value);
+            value_string.push('|');
         }
-        let value_cstring = value_string.to_ascii_cstring();
-        
+        // Descriptions and values may be UTF-8, for translations
+        let value_cstring = value_string.to_utf8_cstring();
+
         retro_variables.push(
             retro_variable { key: key.as_ptr() as *const c_char,
                              value: value_cstring.as_ptr() as *const c_char } );
@@ -184,56 +252,21 @@ pub extern "C" fn retro_set_environment(cb: retro_environment_t)
         descstrings.push(value_cstring);
     }
 
-    retro_variables.push(retro_variable { key: 0u as *const c_char,
-                                          value: 0u as *const c_char } );
-    keystrings.push(String::from_str(""));
+    retro_variables.push(retro_variable { key: ptr::null(),
+                                          value: ptr::null() } );
+    keystrings.push(String::new());
 
-    let mut key_sort= keystrings.clone();
+    let mut key_sort = keystrings.clone();
     key_sort.sort();
     key_sort.dedup();
     if keystrings.len() != key_sort.len() {
         panic!("Duplicate environment variable keys are forbidden. Are you trying to manually implement an automatic environment variable?");
     }
-   
-    unsafe {
-        retro_environment_cb.unwrap()(
-            RETRO_ENVIRONMENT_SET_VARIABLES,
-            retro_variables.as_mut_ptr() as *mut c_void);
-    }
-}
-
-pub enum LogLevel
-{
-    LogDebug = RETRO_LOG_DEBUG as int,
-    LogInfo = RETRO_LOG_INFO as int,
-    LogWarn = RETRO_LOG_WARN as int,
-    LogError = RETRO_LOG_ERROR as int
-}
 
-/// Safely wrapping printf is complicated, so for now only support printing
-/// &strs with utf removed.
-pub fn retro_log(level: LogLevel, text: &str)
-{
-    // TODO copy to the stack if text is short
-    unsafe {
-        let c_text = text.to_ascii_cstring();
-        retro_log_cb.unwrap()(level as i32, "%s\n\0".as_ptr() as *const c_char,
-                              c_text.as_ptr() as *const c_char);
-    }
-}
+    environment(RETRO_ENVIRONMENT_SET_VARIABLES,
+                retro_variables.as_mut_ptr() as *mut c_void);
 
-pub fn retro_log_panic(msg: &str, file: &str, line: uint)
-{
-    // TODO copy to the stack if text is short
-    unsafe {
-        let c_msg = msg.to_ascii_cstring();
-        let c_file = file.to_ascii_cstring();
-        retro_log_cb.unwrap()(
-            LogError as i32, "\"%s\" at %s line %u\n\0".as_ptr() as *const c_char,
-            c_msg.as_ptr() as *const c_char,
-            c_file.as_ptr() as *const c_char,
-            line);
-   }
+    subsystem::register();
 }
 
 fn set_retro_system_av_info(info: &mut retro_system_av_info, fps: f64)
@@ -241,12 +274,10 @@ fn set_retro_system_av_info(info: &mut retro_system_av_info, fps: f64)
     use super::{AV_SCREEN_WIDTH, AV_SCREEN_HEIGHT, AV_PIXEL_ASPECT,
                 AV_SAMPLE_RATE};
 
-    #[static_assert]
-    const _A1: bool = AV_SCREEN_HEIGHT > 0;
-    #[static_assert]
-    const _A2: bool = AV_SCREEN_WIDTH > 0;
-    #[static_assert]
-    const _A3: bool = AV_PIXEL_ASPECT > 0.0;
+    const _: () = assert!(AV_SCREEN_HEIGHT > 0);
+    const _: () = assert!(AV_SCREEN_WIDTH > 0);
+    const _: () = assert!(AV_PIXEL_ASPECT > 0.0);
+    const _: () = assert!(super::CORE_LOGIC_RATE > 0);
 
     info.timing.fps = fps;
     info.timing.sample_rate = AV_SAMPLE_RATE;
@@ -259,127 +290,127 @@ fn set_retro_system_av_info(info: &mut retro_system_av_info, fps: f64)
 
 
 #[no_mangle]
-// Silence false warning, because Rust fails to track variable through transmute
-#[allow(unused_assignments)]
 pub unsafe extern "C" fn retro_get_system_av_info(info: *mut retro_system_av_info)
 {
-    use super::{COLOR_DEPTH_32, CORE_LOGIC_RATE};
+    catch_panic("retro_get_system_av_info", (), || get_system_av_info(&mut *info));
+}
 
-    let frame_mult = get_frame_mult();
-    let mut fps = 60.0;
-    
-    if frame_mult.is_some() {
-        fps = CORE_LOGIC_RATE as u32 as f64 /
-            frame_mult.unwrap() as f64;
-    }
-    else {
-         panic!("Core option error");
-    }
+fn get_system_av_info(info: &mut retro_system_av_info)
+{
+    use super::COLOR_DEPTH_32;
 
-    set_retro_system_av_info(transmute(info), fps);
+    set_retro_system_av_info(info, get_frame_rate().fps());
 
-    let pixel_format: *mut c_void = if COLOR_DEPTH_32 {
-        transmute(&RETRO_PIXEL_FORMAT_XRGB8888)
+    let mut pixel_format = if COLOR_DEPTH_32 {
+        RETRO_PIXEL_FORMAT_XRGB8888
     }
     else {
-        transmute(&RETRO_PIXEL_FORMAT_RGB565)
+        RETRO_PIXEL_FORMAT_RGB565
     };
-    retro_environment_cb.unwrap()(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, pixel_format);
+    environment(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT,
+                &mut pixel_format as *mut c_uint as *mut c_void);
 }
 
 
-/// Gets the current frame multiplier.
+/// The video frame rate, as f64 bits. 60fps until the frame rate option has
+/// been read.
+static CURRENT_FPS: AtomicU64 = AtomicU64::new(0x404E_0000_0000_0000);
+
+/// Current video frame rate, for converting durations to frames.
+pub fn current_fps() -> f64
+{
+    f64::from_bits(CURRENT_FPS.load(Ordering::Relaxed))
+}
+
+/// Gets the current video frame rate.
 /// Caches the current value and only runs the more expensive
-/// get_environment_frame_mult() if a core option has changed.
-fn get_frame_mult() -> Option<u32>
+/// get_environment_frame_rate() if a core option or the region has changed.
+/// The logging options share the change notification, so they are reloaded
+/// here too.
+fn get_frame_rate() -> frame_rate::FrameRate
 {
-    use super::{CORE_LOGIC_RATE};
-    static mut cached_frame_mult: Option<u32> = Some(1);
+    static mut cached_frame_rate: Option<frame_rate::FrameRate> = None;
     static mut first_time: bool = true;
 
     let mut change: u8 = 0;
+    environment(RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE,
+                &mut change as *mut u8 as *mut c_void);
+
     unsafe
     {
-        retro_environment_cb.unwrap()(
-            RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE,
-            transmute(&change));
-     
-        if first_time || change != 0
+        if first_time || change != 0 || region::take_changed()
         {
             first_time = false;
-            let new_frame_mult = get_environment_frame_mult();
-            if new_frame_mult == cached_frame_mult {
+            log::load_options();
+            frame_rate::load_options();
+            input::load_options();
+            rewind::load_options();
+            check::load_options();
+            cheat::load_options();
+            lang::load_options();
+            perf::load_options();
+            let new_frame_rate = get_environment_frame_rate();
+            if Some(new_frame_rate) == cached_frame_rate {
                 change = 0;
             }
             else {
-                cached_frame_mult = new_frame_mult;
+                cached_frame_rate = Some(new_frame_rate);
+                let fps = new_frame_rate.fps();
+                CURRENT_FPS.store(fps.to_bits(), Ordering::Relaxed);
+                frame_rate::reset_pacing();
+                frame_rate::set_frame_time_callback();
+                if new_frame_rate.is_exact() {
+                    log_info!("Frame rate set to {:.2} fps, {} updates per frame",
+                              fps, new_frame_rate.ticks_for_frame(0));
+                }
+                else {
+                    log_info!("Frame rate set to {:.2} fps, {:.2} updates per frame on average",
+                              fps, region::core_logic_rate() as f64 / fps);
+                }
             }
         }
 
+        let frame_rate = cached_frame_rate.unwrap();
         if change != 0
         {
-            let info: retro_system_av_info = core::mem::uninitialized();
-            set_retro_system_av_info(transmute(&info), CORE_LOGIC_RATE as u32 as f64 /
-                               cached_frame_mult.unwrap() as f64);
-            retro_environment_cb.unwrap()(
-                RETRO_ENVIRONMENT_SET_SYSTEM_AV_INFO,
-                transmute(&info));
+            let mut info = retro_system_av_info::default();
+            set_retro_system_av_info(&mut info, frame_rate.fps());
+            environment(RETRO_ENVIRONMENT_SET_SYSTEM_AV_INFO,
+                        &mut info as *mut _ as *mut c_void);
         }
 
-        return cached_frame_mult;
+        frame_rate
     }
 }
 
-fn get_environment_frame_mult() -> Option<u32>
+/// Gets the value of a core option, given its null terminated key.
+fn get_variable(key: &str) -> Option<String>
 {
-    use super::CORE_LOGIC_RATE;
-        let get_variable =
-        retro_variable {key: FRAME_RATE_KEY.as_ptr() as *const i8,
-                        value: 0u as *const c_char};
-    
-    unsafe { retro_environment_cb.unwrap()(RETRO_ENVIRONMENT_GET_VARIABLE,
-                                           transmute(&get_variable)); }
-
-    let refresh_rate =
-        unsafe {core::str::raw::c_str_to_static_slice(get_variable.value) };
-
-    match CORE_LOGIC_RATE {
-        LogicRate60 =>
-            match refresh_rate {
-                "30" => Some(2u32),
-                "60" => Some(1u32),
-                _ => None,
-            },
-        LogicRate120 =>
-            match refresh_rate {
-                "30" => Some(4u32),
-                "60" => Some(2u32),
-                "120" => Some(1u32),
-                _ => None,
-            },
-        LogicRate720 =>
-            match refresh_rate {
-                "24" => Some(30u32),
-                "30" => Some(24u32),
-                "48" => Some(15u32),
-                "51.4" => Some(14u32),
-                "60" => Some(12u32),
-                "72" => Some(10u32),
-                "80" => Some(9u32),
-                "90" => Some(8u32),
-                "102.9" => Some(7u32),
-                "120" => Some(6u32),
-                "144" => Some(5u32),
-                "180" => Some(4u32),
-                "240" => Some(3u32),
-                _ => None,
-            }
+    let mut variable =
+        retro_variable {key: key.as_ptr() as *const c_char,
+                        value: ptr::null()};
+
+    if !environment(RETRO_ENVIRONMENT_GET_VARIABLE,
+                    &mut variable as *mut _ as *mut c_void) ||
+       variable.value.is_null() {
+        return None;
     }
+    unsafe { CStr::from_ptr(variable.value) }.to_str().ok().map(String::from)
+}
+
+fn get_environment_frame_rate() -> frame_rate::FrameRate
+{
+    frame_rate::frame_rate_for(get_variable(frame_rate::FRAME_RATE_KEY).as_deref())
 }
 
 
 #[no_mangle]
 pub unsafe extern "C" fn retro_get_system_info(info: *mut retro_system_info)
+{
+    catch_panic("retro_get_system_info", (), || get_system_info(&mut *info));
+}
+
+fn get_system_info(info: &mut retro_system_info)
 {
     use super::{CORE_NAME, CORE_VERSION, VALID_EXTENSIONS};
 
@@ -387,40 +418,38 @@ pub unsafe extern "C" fn retro_get_system_info(info: *mut retro_system_info)
     {
         retro_string.check_valid();
     }
-   
-    (*info).library_name     = CORE_NAME.as_ptr() as *const i8;
-    (*info).library_version  = CORE_VERSION.as_ptr() as *const i8;
-    (*info).valid_extensions = VALID_EXTENSIONS.as_ptr() as *const i8;
-    (*info).need_fullpath    = false as u8;
-    (*info).block_extract    = false as u8;
+
+    info.library_name     = CORE_NAME.as_ptr() as *const c_char;
+    info.library_version  = CORE_VERSION.as_ptr() as *const c_char;
+    info.valid_extensions = VALID_EXTENSIONS.as_ptr() as *const c_char;
+    info.need_fullpath    = false as u8;
+    info.block_extract    = false as u8;
 }
 
 trait RetroString
 {
     fn check_valid(self);
     fn to_ascii_cstring(self) -> String;
+    fn to_utf8_cstring(self) -> String;
 }
 
-impl<'a> RetroString for &'a str
+impl RetroString for &str
 {
     fn check_valid(self)
     {
-        for b in self.as_bytes().iter()
-        {
-            if (*b & 0x80) != 0 {
-                panic!("All libretro strings must be ascii.");
-            }
+        if !self.is_ascii() {
+            panic!("All libretro strings must be ascii.");
         }
-        if self.as_bytes()[self.len() - 1] != 0u8 {
+        if self.as_bytes().last() != Some(&0u8) {
             panic!("All libretro strings must be null terminated.");
         }
     }
     fn to_ascii_cstring(self) -> String
     {
-        let terminated_max_len = self.as_bytes().len() + 1;
-        
+        let terminated_max_len = self.len() + 1;
+
         let mut dst = String::with_capacity(terminated_max_len);
-        
+
         for src_byte in self.bytes()
         {
             if (src_byte & 0x80) == 0
@@ -430,40 +459,91 @@ impl<'a> RetroString for &'a str
         }
         dst.push('\0');
         dst
-    }    
+    }
+    fn to_utf8_cstring(self) -> String
+    {
+        let mut dst = self.replace('\0', "");
+        dst.push('\0');
+        dst
+    }
 }
 
 
 
+static VIDEO_FRAME: AtomicU64 = AtomicU64::new(0);
+
+/// Number of video frames run so far.
+pub fn video_frame() -> u64
+{
+    VIDEO_FRAME.load(Ordering::Relaxed)
+}
+
 #[no_mangle]
-pub extern fn retro_run()
+pub extern "C" fn retro_run()
 {
-    use super::{AV_SCREEN_WIDTH, AV_SCREEN_HEIGHT, COLOR_DEPTH_32};
+    catch_panic("retro_run", (), run);
+}
 
-    unsafe {VIDEO_LOCK.lock_noguard();}
-    
-    // For now, poll input hardware only once per displayed frame
-    // (InputState::poll uses cached values)
-    // libretro version 2 will support polling every logic update
-    unsafe {retro_input_poll_cb.unwrap()();}
-    for i in range(0, get_frame_mult().unwrap()) {
-        if i==0 {
-
-            // TODO set the video latency
-            // Currently set to maximum possible
-
-            super::snapshot_video();
-            super::render_video();
-            unsafe {VIDEO_LOCK.unlock_noguard();}
-            unsafe {
-                let guard = VIDEO_WAIT.lock();
-                guard.signal();
-            }
-       }
-       super::core_run();
+fn run()
+{
+    if panic::is_faulted() {
+        panic::request_shutdown();
+        show_error_screen();
+        return;
     }
+    VIDEO_FRAME.fetch_add(1, Ordering::Relaxed);
+
+    // Input is polled at the start of the frame, even if no update is due,
+    // and optionally again before every later update
+    // (InputState::poll uses the values cached by the last poll)
+    input::poll_for_tick(0);
+
+    // The number of logic updates varies from frame to frame when the frame
+    // rate doesn't divide CORE_LOGIC_RATE, and may be zero with real time
+    // pacing, which repeats the last frame. Rewinding replaces the updates
+    // with a step back to an earlier state.
+    let frame_rate = get_frame_rate();
+    let ticks = if rewind::rewind_frame() {
+        0
+    }
+    else {
+        frame_rate::next_frame_ticks(frame_rate)
+    };
+
+    // TODO set the video latency
+    // Currently set to maximum possible
+
+    VIDEO.wait_for_render();
+    {
+        perf_scope!("snapshot_video");
+        super::snapshot_video();
+    }
+    VIDEO.request_render();
+    check::begin_frame();
+    for i in 0..ticks {
+        if i > 0 {
+            input::poll_for_tick(i);
+        }
+        {
+            perf_scope!("core_run");
+            super::core_run();
+        }
+        cheat::apply();
+        input::end_tick();
+        check::after_tick();
+        rewind::after_tick();
+    }
+    check::end_frame();
+
+    VIDEO.wait_for_render();
+    perf::end_frame();
+    refresh_video();
+}
+
+fn refresh_video()
+{
+    use super::{AV_SCREEN_WIDTH, AV_SCREEN_HEIGHT, COLOR_DEPTH_32};
 
-    unsafe {VIDEO_LOCK.lock_noguard();}
     unsafe {
         retro_video_refresh_cb.unwrap()(frame_buf as *const c_void,
                                         AV_SCREEN_WIDTH,
@@ -471,42 +551,137 @@ pub extern fn retro_run()
                                         (AV_SCREEN_WIDTH *
                                          if COLOR_DEPTH_32 {4} else {2}) as size_t);
     }
-    unsafe {VIDEO_LOCK.unlock_noguard();}
- 
 }
-pub static mut frame_buf: *mut c_void = 0i as *mut c_void;
+
+/// Replaces the core's video output once it has faulted. Core logic is no
+/// longer run, so the frontend is shown a plain dark red frame until it acts
+/// on the shutdown request.
+fn show_error_screen()
+{
+    use super::COLOR_DEPTH_32;
+
+    VIDEO.wait_for_render();
+    if unsafe { frame_buf }.is_null() {
+        return;
+    }
+    let pixels = unsafe {
+        std::slice::from_raw_parts_mut(frame_buf as *mut u32, frame_buf_words())
+    };
+    // RGB565 pixels are packed two per word
+    pixels.fill(if COLOR_DEPTH_32 { 0x0080_0000 } else { 0x8000_8000 });
+    refresh_video();
+}
+pub static mut frame_buf: *mut c_void = ptr::null_mut();
+
+/// Length of frame_buf in u32 words, so the buffer is aligned for both pixel
+/// formats.
+fn frame_buf_words() -> usize
+{
+    use super::{AV_SCREEN_WIDTH, AV_SCREEN_HEIGHT, COLOR_DEPTH_32};
+
+    let pixels = AV_SCREEN_WIDTH as usize * AV_SCREEN_HEIGHT as usize;
+    if COLOR_DEPTH_32 { pixels } else { pixels.div_ceil(2) }
+}
 
 #[no_mangle]
 pub unsafe extern "C" fn retro_init()
 {
-    use super::{AV_SCREEN_WIDTH, AV_SCREEN_HEIGHT, COLOR_DEPTH_32};
-  
-    frame_buf = libc::malloc(((AV_SCREEN_WIDTH as uint) *
-                              (AV_SCREEN_HEIGHT as uint)) as u64 *
-                             if COLOR_DEPTH_32 {core::mem::size_of::<u32>()}
-                             else {core::mem::size_of::<u16>()} as u64);
+    catch_panic("retro_init", (), || init());
+}
+
+unsafe fn init()
+{
+    panic::clear_fault();
+    input::reset_ticks();
+
+    let buf = vec![0u32; frame_buf_words()].into_boxed_slice();
+    frame_buf = Box::into_raw(buf) as *mut c_void;
 
     // start video thread
-    thread::Thread::spawn(video_thread);
+    VIDEO.start();
 }
 
 
-static VIDEO_SHUTDOWN: AtomicBool = INIT_ATOMIC_BOOL;
-static VIDEO_LOCK: mutex::StaticNativeMutex = mutex::NATIVE_MUTEX_INIT;
-static VIDEO_WAIT: mutex::StaticNativeMutex = mutex::NATIVE_MUTEX_INIT;
+struct VideoState
+{
+    render_pending: bool,
+    shutdown: bool,
+}
 
-fn video_thread()
+/// Hands snapshots from retro_run() to the video thread, so render_video()
+/// runs concurrently with core_run().
+struct VideoThread
 {
-    loop
+    state: Mutex<VideoState>,
+    wake: Condvar,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+static VIDEO: VideoThread = VideoThread {
+    state: Mutex::new(VideoState { render_pending: false, shutdown: false }),
+    wake: Condvar::new(),
+    handle: Mutex::new(None),
+};
+
+impl VideoThread
+{
+    fn start(&'static self)
+    {
+        self.state.lock().unwrap().shutdown = false;
+        let handle = thread::Builder::new()
+            .name("video".to_string())
+            .spawn(move || self.run())
+            .expect("Failed to start video thread");
+        *self.handle.lock().unwrap() = Some(handle);
+    }
+
+    fn run(&self)
+    {
+        let mut state = self.state.lock().unwrap();
+        loop
+        {
+            while !state.render_pending && !state.shutdown {
+                state = self.wake.wait(state).unwrap();
+            }
+            if state.shutdown { break; }
+            drop(state);
+            // The environment callback may only be used from the frontend's
+            // thread, so retro_run() requests the shutdown for us.
+            if !panic::is_faulted() &&
+               panic::catch_unwind(|| {
+                   perf_scope!("render_video");
+                   super::render_video();
+               }).is_err() {
+                panic::enter_fault_state("render_video");
+            }
+            state = self.state.lock().unwrap();
+            state.render_pending = false;
+            self.wake.notify_all();
+        }
+    }
+
+    fn request_render(&self)
     {
-        unsafe {
-            let guard = VIDEO_WAIT.lock();
-            guard.wait();
+        self.state.lock().unwrap().render_pending = true;
+        self.wake.notify_all();
+    }
+
+    fn wait_for_render(&self)
+    {
+        let mut state = self.state.lock().unwrap();
+        while state.render_pending {
+            state = self.wake.wait(state).unwrap();
         }
-        if VIDEO_SHUTDOWN.load(SeqCst) { break; }
-        unsafe {VIDEO_LOCK.lock_noguard();}
-        super::render_video();
-        unsafe {VIDEO_LOCK.unlock_noguard();}
+    }
+
+    fn stop(&self)
+    {
+        self.state.lock().unwrap().shutdown = true;
+        self.wake.notify_all();
+        if let Some(handle) = self.handle.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+        self.state.lock().unwrap().render_pending = false;
     }
 }
 
@@ -514,44 +689,170 @@ fn video_thread()
 #[no_mangle]
 pub unsafe extern "C" fn retro_deinit()
 {
-    VIDEO_SHUTDOWN.store(true, SeqCst);
-    {
-        let guard = VIDEO_WAIT.lock();
-        guard.signal();
+    catch_panic("retro_deinit", (), || deinit());
+    panic::uninstall_panic_hook();
+}
+
+unsafe fn deinit()
+{
+    VIDEO.stop();
+    perf::log_totals();
+    if !frame_buf.is_null() {
+        let buf = ptr::slice_from_raw_parts_mut(frame_buf as *mut u32,
+                                                frame_buf_words());
+        drop(Box::from_raw(buf));
+        frame_buf = ptr::null_mut();
     }
-    VIDEO_LOCK.destroy();
-    if frame_buf != 0u8 as *mut c_void { libc::free(frame_buf); }
-}       
+}
 
 
 // implement stubs for mandatory extern functions
 
 #[no_mangle]
-pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
-#[no_mangle]
-pub extern "C" fn retro_reset() {}
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint)
+{
+    catch_panic("retro_set_controller_port_device", (), || ());
+}
 #[no_mangle]
-pub extern "C" fn retro_serialize_size() -> size_t { 0 }
+pub extern "C" fn retro_reset()
+{
+    catch_panic("retro_reset", (), || {
+        movie::finish();
+        rewind::clear();
+        rng::reseed();
+        frame_rate::reset_pacing();
+        input::reset_ticks();
+        movie::start();
+    });
+}
 #[no_mangle]
-pub extern "C" fn retro_serialize(_data: *mut u8, _size: size_t) -> u8 { false as u8 }
+pub extern "C" fn retro_serialize_size() -> size_t
+{
+    catch_panic("retro_serialize_size", 0, serialize::state_size)
+}
 #[no_mangle]
-pub extern "C" fn retro_unserialize(_data: *const u8, _size: size_t) -> u8 { false as u8 }
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: size_t) -> u8
+{
+    catch_panic("retro_serialize", false as u8, || {
+        serialize::save(slice::from_raw_parts_mut(data as *mut u8, size)) as u8
+    })
+}
 #[no_mangle]
-pub extern "C" fn retro_cheat_reset() {}
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: size_t) -> u8
+{
+    catch_panic("retro_unserialize", false as u8, || {
+        // Jumping to another state would leave the movie out of step with
+        // the game, as with rewinding
+        if movie::is_active() {
+            log_warn!("Save state not loaded while an input recording is active");
+            notify("Save states can't be loaded while input recording is on", 3.0);
+            return false as u8;
+        }
+        let loaded = serialize::load(slice::from_raw_parts(data as *const u8, size));
+        if loaded {
+            rewind::clear();
+        }
+        loaded as u8
+    })
+}
 #[no_mangle]
-pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: u8, _code: *const u8) {}
+pub extern "C" fn retro_cheat_reset()
+{
+    catch_panic("retro_cheat_reset", (), cheat::reset);
+}
 #[no_mangle]
-pub extern "C" fn retro_load_game_special(_type: c_uint, _info: *const retro_game_info, _num: size_t) -> u8 { false as u8 }
+pub unsafe extern "C" fn retro_cheat_set(index: c_uint, enabled: u8, code: *const c_char)
+{
+    catch_panic("retro_cheat_set", (), || {
+        if !code.is_null() {
+            cheat::set(index, enabled != 0, &CStr::from_ptr(code).to_string_lossy());
+        }
+    });
+}
 #[no_mangle]
-pub extern "C" fn retro_unload_game() {}
+pub unsafe extern "C" fn retro_load_game_special(game_type: c_uint, info: *const retro_game_info, num: size_t) -> u8
+{
+    catch_panic("retro_load_game_special", false as u8, || {
+        load_game(None, || subsystem::load(game_type, info, num)) as u8
+    })
+}
 #[no_mangle]
-pub extern "C" fn retro_get_region() -> c_uint { RETRO_REGION_NTSC }
+pub extern "C" fn retro_unload_game()
+{
+    catch_panic("retro_unload_game", (), || {
+        movie::finish();
+        memory::clear();
+        disk::clear();
+    });
+}
 #[no_mangle]
-pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut u8 { core::ptr::null_mut() }
+pub extern "C" fn retro_get_region() -> c_uint
+{
+    catch_panic("retro_get_region", RETRO_REGION_NTSC, region::retro_region)
+}
 #[no_mangle]
-pub extern "C" fn retro_get_memory_size(_id: c_uint) -> size_t { 0 }
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void
+{
+    catch_panic("retro_get_memory_data", ptr::null_mut(), || memory::data(id))
+}
 #[no_mangle]
-pub extern "C" fn retro_load_game(_info: *const u8) -> u8 { true as u8}
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> size_t
+{
+    catch_panic("retro_get_memory_size", 0, || memory::size(id))
+}
 #[no_mangle]
-pub extern "C" fn retro_api_version() -> c_uint { 1 }
+pub unsafe extern "C" fn retro_load_game(info: *const retro_game_info) -> u8
+{
+    catch_panic("retro_load_game", false as u8, || {
+        let info = info.as_ref();
+        let content = GameContent {
+            path: info.filter(|info| !info.path.is_null())
+                .map(|info| CStr::from_ptr(info.path).to_string_lossy().into_owned()),
+            data: match info {
+                Some(info) if !info.data.is_null() =>
+                    slice::from_raw_parts(info.data as *const u8, info.size),
+                _ => &[],
+            },
+        };
+        movie::set_content([(content.path.as_deref(), content.data)]);
+        load_game(content.path.as_deref(), || super::core_load_game(&content)) as u8
+    })
+}
 
+/// Loads content with core_load, which calls the core, and sets up the
+/// wrapper around it.
+fn load_game(path: Option<&str>, core_load: impl FnOnce() -> bool) -> bool
+{
+    paths::load(path);
+    memory::clear();
+    region::clear();
+    // Movies count ticks and frames from the start of the content
+    input::reset_ticks();
+    frame_rate::reset_pacing();
+    disk::load(path);
+    // Text built while loading should be in the chosen language
+    lang::load_options();
+    if !core_load() {
+        log_error!("Content not loaded");
+        notify("Content couldn't be loaded", 3.0);
+        return false;
+    }
+    region::load();
+    memory::submit_map();
+    disk::register();
+
+    // Read the frame rate option, which sends the frame time callback if the
+    // rate changed, and send it anyway in case it's the same as for the last
+    // content
+    get_frame_rate();
+    frame_rate::set_frame_time_callback();
+    movie::start();
+    rewind::clear();
+    rng::reseed();
+    true
+}
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint
+{
+    catch_panic("retro_api_version", RETRO_API_VERSION, || RETRO_API_VERSION)
+}
@@ -0,0 +1,328 @@
+//! Input recordings, or movies.
+//!
+//! A movie records the input returned by InputState::poll for every logic
+//! update, not every video frame, so playing it back runs the core through
+//! the same states whatever the frame rate, frame pacing or input polling
+//! options are set to. Only changes are stored: an entry gives a player's
+//! buttons from one logic update onwards, and a player with no entry yet has
+//! nothing pressed.
+//!
+//! The input recording core option chooses whether to record or play back.
+//! It is read when content is loaded, and the movie covers everything from
+//! then until the content is unloaded. A reset saves the recording and starts
+//! a new one, or plays the movie back from the start again. Movies are kept
+//! in the frontend's save directory, named after the content, and only play
+//! back on the content they were recorded from.
+//!
+//! The file is little endian: the magic bytes, the format version, the core
+//! logic rate, a hash of the content, the length in logic updates and the
+//! number of entries, then the entries as a logic update, a player and a
+//! button bitmask.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use super::{check, get_variable, notify, paths, region, tick_time};
+
+const MOVIE_MAGIC: &[u8; 8] = b"RLMOVIE\0";
+const MOVIE_VERSION: u32 = 2;
+const HEADER_LEN: usize = 36;
+const ENTRY_LEN: usize = 14;
+
+const INPUT_RECORDING_KEY: &str = "input_recording\0";
+const RECORD: &str = "record";
+const PLAY_BACK: &str = "play back";
+
+/// Buttons pressed by a player from a logic update onwards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Entry
+{
+    tick: u64,
+    player: u32,
+    buttons: u16,
+}
+
+enum Movie
+{
+    Off,
+    Recording { entries: Vec<Entry>, buttons: HashMap<u32, u16> },
+    Playing { entries: Vec<Entry>, next: usize, length: u64, buttons: HashMap<u32, u16> },
+}
+
+/// The loaded content, as a name for the movie file and a hash.
+struct ContentId
+{
+    name: String,
+    hash: u64,
+}
+
+static MOVIE: Mutex<Movie> = Mutex::new(Movie::Off);
+static CONTENT: Mutex<ContentId> = Mutex::new(ContentId { name: String::new(), hash: 0 });
+
+fn lock() -> MutexGuard<'static, Movie>
+{
+    MOVIE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn content() -> MutexGuard<'static, ContentId>
+{
+    CONTENT.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Notes the content being loaded, as the path and data of each piece, before
+/// start(). Movies are named after the first piece with a path. Content
+/// passed by path alone is told apart by its file name.
+pub fn set_content<'a>(pieces: impl IntoIterator<Item = (Option<&'a str>, &'a [u8])>)
+{
+    let mut name = None;
+    let mut hashes = Vec::new();
+    for (path, data) in pieces {
+        let stem = path.and_then(|path| Path::new(path).file_stem())
+            .map(|stem| stem.to_string_lossy().into_owned());
+        let hash = if data.is_empty() {
+            check::hash(stem.as_deref().unwrap_or("").as_bytes())
+        } else {
+            check::hash(data)
+        };
+        hashes.extend_from_slice(&hash.to_le_bytes());
+        name = name.or(stem);
+    }
+    let name = name.unwrap_or_default().chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    *content() = ContentId { name, hash: check::hash(&hashes) };
+}
+
+/// The movie file for the loaded content.
+fn file_name() -> String
+{
+    let content = content();
+    if content.name.is_empty() {
+        paths::core_file_name("movie")
+    } else {
+        paths::core_file_name(&format!("{}.movie", content.name))
+    }
+}
+
+/// Builds the input recording option as null terminated key and value
+/// strings for RETRO_ENVIRONMENT_SET_VARIABLES.
+pub fn variable() -> (String, String)
+{
+    (INPUT_RECORDING_KEY.to_string(),
+     format!("Input recording; off|{}|{}\0", RECORD, PLAY_BACK))
+}
+
+/// Starts recording or playing back, as chosen by the input recording option.
+/// Called when content is loaded and after a reset.
+pub fn start()
+{
+    let mode = get_variable(INPUT_RECORDING_KEY);
+    let mut movie = lock();
+    *movie = Movie::Off;
+    match mode.as_deref() {
+        Some(RECORD) => {
+            log_info!("Recording input");
+            *movie = Movie::Recording { entries: Vec::new(), buttons: HashMap::new() };
+        }
+        Some(PLAY_BACK) => {
+            let name = file_name();
+            let hash = content().hash;
+            match paths::read_save_file(&name).map_err(|e| e.to_string())
+                .and_then(|data| parse(&data, hash)) {
+                Ok((entries, length)) => {
+                    log_info!("Playing back {} logic updates of input from {}", length, name);
+                    *movie = Movie::Playing { entries, next: 0, length,
+                                              buttons: HashMap::new() };
+                }
+                Err(error) => {
+                    log_error!("Can't play back {}: {}", name, error);
+                    notify("Can't play back the input recording", 3.0);
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Stops recording or playing back, saving the recording. Called when the
+/// content is unloaded or the core is reset.
+pub fn finish()
+{
+    let movie = std::mem::replace(&mut *lock(), Movie::Off);
+    if let Movie::Recording { entries, .. } = movie {
+        let name = file_name();
+        let length = tick_time().tick;
+        let hash = content().hash;
+        match paths::write_save_file(&name, &serialize(&entries, length, hash)) {
+            Ok(()) => log_info!("Saved {} logic updates of input to {}", length, name),
+            Err(error) => log_error!("Can't save {}: {}", name, error),
+        }
+    }
+}
+
+/// Whether input is being recorded or played back.
+pub fn is_active() -> bool
+{
+    !matches!(*lock(), Movie::Off)
+}
+
+/// Returns the recorded buttons for a player in the current logic update, or
+/// None to read the frontend's input. Live input is passed to record().
+pub fn playback(player: u32) -> Option<u16>
+{
+    let tick = tick_time().tick;
+    let mut movie = lock();
+    let (entries, next, length, buttons) = match &mut *movie {
+        Movie::Playing { entries, next, length, buttons } => (entries, next, *length, buttons),
+        _ => return None,
+    };
+    if tick >= length {
+        *movie = Movie::Off;
+        log_info!("Input playback finished");
+        notify("Input playback finished", 3.0);
+        return None;
+    }
+    while let Some(entry) = entries.get(*next).filter(|entry| entry.tick <= tick) {
+        buttons.insert(entry.player, entry.buttons);
+        *next += 1;
+    }
+    Some(buttons.get(&player).copied().unwrap_or(0))
+}
+
+/// Records the buttons a player has pressed in the current logic update.
+pub fn record(player: u32, pressed: u16)
+{
+    if let Movie::Recording { entries, buttons } = &mut *lock() {
+        if buttons.get(&player).copied().unwrap_or(0) != pressed {
+            buttons.insert(player, pressed);
+            entries.push(Entry { tick: tick_time().tick, player, buttons: pressed });
+        }
+    }
+}
+
+fn serialize(entries: &[Entry], length: u64, content_hash: u64) -> Vec<u8>
+{
+    let mut data = Vec::with_capacity(HEADER_LEN + entries.len() * ENTRY_LEN);
+    data.extend_from_slice(MOVIE_MAGIC);
+    data.extend_from_slice(&MOVIE_VERSION.to_le_bytes());
+    data.extend_from_slice(&region::core_logic_rate().to_le_bytes());
+    data.extend_from_slice(&content_hash.to_le_bytes());
+    data.extend_from_slice(&length.to_le_bytes());
+    data.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    for entry in entries {
+        data.extend_from_slice(&entry.tick.to_le_bytes());
+        data.extend_from_slice(&entry.player.to_le_bytes());
+        data.extend_from_slice(&entry.buttons.to_le_bytes());
+    }
+    data
+}
+
+fn parse(data: &[u8], content_hash: u64) -> Result<(Vec<Entry>, u64), String>
+{
+    let u16_at = |at: usize| u16::from_le_bytes(data[at..at + 2].try_into().unwrap());
+    let u32_at = |at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+    let u64_at = |at: usize| u64::from_le_bytes(data[at..at + 8].try_into().unwrap());
+
+    if data.len() < HEADER_LEN || &data[..8] != MOVIE_MAGIC {
+        return Err("not an input recording".to_string());
+    }
+    let version = u32_at(8);
+    if version != MOVIE_VERSION {
+        return Err(format!("unsupported version {}", version));
+    }
+    let logic_rate = u32_at(12);
+    if logic_rate != region::core_logic_rate() {
+        return Err(format!("recorded at {}Hz core logic rate, not {}Hz",
+                           logic_rate, region::core_logic_rate()));
+    }
+    if u64_at(16) != content_hash {
+        return Err("recorded from other content".to_string());
+    }
+    let length = u64_at(24);
+    let count = u32_at(32) as usize;
+    let len = count.checked_mul(ENTRY_LEN).and_then(|len| len.checked_add(HEADER_LEN));
+    if len.is_none_or(|len| len > data.len()) {
+        return Err("truncated".to_string());
+    }
+    if len != Some(data.len()) {
+        return Err("wrong length".to_string());
+    }
+    let entries = (0..count)
+        .map(|i| HEADER_LEN + i * ENTRY_LEN)
+        .map(|at| Entry { tick: u64_at(at), player: u32_at(at + 8), buttons: u16_at(at + 12) })
+        .collect();
+    Ok((entries, length))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const HASH: u64 = 0x0123_4567_89ab_cdef;
+
+    fn entries() -> Vec<Entry>
+    {
+        vec![Entry { tick: 0, player: 0, buttons: 0x0001 },
+             Entry { tick: 5, player: 1, buttons: 0x8000 },
+             Entry { tick: 1 << 40, player: 0, buttons: 0 }]
+    }
+
+    #[test]
+    fn round_trip()
+    {
+        let data = serialize(&entries(), 12345, HASH);
+        assert_eq!(data.len(), HEADER_LEN + 3 * ENTRY_LEN);
+        let (parsed, length) = parse(&data, HASH).unwrap();
+        assert_eq!(length, 12345);
+        assert_eq!(parsed, entries());
+        let (parsed, length) = parse(&serialize(&[], 0, HASH), HASH).unwrap();
+        assert!(parsed.is_empty());
+        assert_eq!(length, 0);
+    }
+
+    #[test]
+    fn truncated_files()
+    {
+        let data = serialize(&entries(), 100, HASH);
+        for len in [0, 7, HEADER_LEN - 1, HEADER_LEN, data.len() - ENTRY_LEN, data.len() - 1] {
+            assert!(parse(&data[..len], HASH).is_err(), "{} bytes accepted", len);
+        }
+        assert_eq!(parse(&data[..data.len() - 1], HASH).unwrap_err(), "truncated");
+    }
+
+    #[test]
+    fn corrupt_files()
+    {
+        let data = serialize(&entries(), 100, HASH);
+
+        let mut bad_magic = data.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(parse(&bad_magic, HASH).unwrap_err(), "not an input recording");
+
+        let mut bad_version = data.clone();
+        bad_version[8] = 1;
+        assert_eq!(parse(&bad_version, HASH).unwrap_err(), "unsupported version 1");
+
+        let mut bad_rate = data.clone();
+        bad_rate[12..16].copy_from_slice(&(region::core_logic_rate() + 1).to_le_bytes());
+        assert!(parse(&bad_rate, HASH).unwrap_err().contains("core logic rate"));
+
+        // A huge count must not overflow working out the length
+        let mut bad_count = data.clone();
+        bad_count[32..36].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(parse(&bad_count, HASH).unwrap_err(), "truncated");
+
+        let mut extra = data.clone();
+        extra.push(0);
+        assert_eq!(parse(&extra, HASH).unwrap_err(), "wrong length");
+    }
+
+    #[test]
+    fn other_content_is_refused()
+    {
+        let data = serialize(&entries(), 100, HASH);
+        assert_eq!(parse(&data, HASH + 1).unwrap_err(), "recorded from other content");
+    }
+}
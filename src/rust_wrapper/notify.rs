@@ -0,0 +1,47 @@
+//! On-screen messages through RETRO_ENVIRONMENT_SET_MESSAGE.
+
+use core::ffi::{c_uint, c_void};
+use std::ffi::CString;
+use std::sync::{Mutex, PoisonError};
+
+use super::libretro::*;
+use super::log::to_log_cstring;
+use super::{current_fps, environment, video_frame};
+
+struct Shown
+{
+    // Frontends copy the message, but keep it alive until it is replaced in
+    // case one does not.
+    text: CString,
+    until_frame: u64,
+}
+
+static LAST: Mutex<Option<Shown>> = Mutex::new(None);
+
+/// Shows text on screen for the given number of seconds, converted to frames
+/// at the current frame rate. A message identical to the one still on screen
+/// is not shown again, so it is safe to call every frame. Returns false if
+/// the frontend can't display messages.
+///
+/// Uses the environment callback, so call it from core_run() or other code
+/// on the frontend's thread, never from render_video().
+pub fn notify(text: &str, seconds: f32) -> bool
+{
+    let frames = (seconds as f64 * current_fps()).round().max(1.0) as c_uint;
+    let now = video_frame();
+
+    let text = to_log_cstring(text);
+
+    let mut last = LAST.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(ref shown) = *last {
+        if shown.text == text && now < shown.until_frame {
+            return true;
+        }
+    }
+
+    let mut message = retro_message { msg: text.as_ptr(), frames };
+    let shown = environment(RETRO_ENVIRONMENT_SET_MESSAGE,
+                            &mut message as *mut _ as *mut c_void);
+    *last = Some(Shown { text, until_frame: now + frames as u64 });
+    shown
+}
@@ -0,0 +1,164 @@
+use core::cell::Cell;
+use core::ptr;
+use std::backtrace::Backtrace;
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use super::libretro::RETRO_ENVIRONMENT_SHUTDOWN;
+use super::{environment, notify};
+use super::log::{retro_log, retro_log_panic, LogLevel};
+
+type Hook = Box<dyn Fn(&PanicHookInfo) + Sync + Send + 'static>;
+
+static FAULTED: AtomicBool = AtomicBool::new(false);
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+static HOOK_INSTALLED: AtomicBool = AtomicBool::new(false);
+/// The host process's panic hook, which ours passes its own panics on to.
+static PREVIOUS_HOOK: Mutex<Option<Hook>> = Mutex::new(None);
+
+thread_local! {
+    /// How many catch_unwind() calls this thread is in, so only the core's
+    /// panics go to the frontend log.
+    static CATCHING: Cell<u32> = const { Cell::new(0) };
+}
+
+fn previous_hook() -> MutexGuard<'static, Option<Hook>>
+{
+    PREVIOUS_HOOK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Routes the core's panic messages, with a backtrace, to the frontend log.
+/// Other panics in the host process still go to its own hook.
+pub fn install_panic_hook()
+{
+    if HOOK_INSTALLED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    *previous_hook() = Some(panic::take_hook());
+    panic::set_hook(Box::new(panic_hook));
+}
+
+/// Puts back the host process's panic hook, in retro_deinit(), as the core
+/// may be unloaded next.
+pub fn uninstall_panic_hook()
+{
+    if !HOOK_INSTALLED.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    drop(panic::take_hook());
+    let previous = previous_hook().take();
+    if let Some(previous) = previous {
+        panic::set_hook(previous);
+    }
+}
+
+fn panic_hook(info: &PanicHookInfo)
+{
+    if CATCHING.get() == 0 {
+        if let Some(previous) = &*previous_hook() {
+            previous(info);
+        }
+        return;
+    }
+    let payload = info.payload();
+    let msg = match payload.downcast_ref::<&str>() {
+        Some(msg) => *msg,
+        None => match payload.downcast_ref::<String>() {
+            Some(msg) => msg.as_str(),
+            None => "Box<dyn Any>",
+        },
+    };
+    let (file, line) = match info.location() {
+        Some(location) => (location.file(), location.line()),
+        None => ("<unknown>", 0),
+    };
+    let backtrace = Backtrace::force_capture().to_string();
+    retro_log_panic(msg, file, line, &backtrace);
+}
+
+/// Runs body, stopping any panic, which is logged by our panic hook.
+pub fn catch_unwind<R>(body: impl FnOnce() -> R) -> thread::Result<R>
+{
+    CATCHING.set(CATCHING.get() + 1);
+    let result = panic::catch_unwind(AssertUnwindSafe(body));
+    CATCHING.set(CATCHING.get() - 1);
+    result
+}
+
+/// Runs the body of an exported retro_* function, stopping any panic at the
+/// FFI boundary. A panic faults the core, asks the frontend to shut down and
+/// returns `fallback` in place of the body's result.
+pub fn catch_panic<R, F: FnOnce() -> R>(entry: &str, fallback: R, body: F) -> R
+{
+    // Some entry points may be called before retro_set_environment()
+    install_panic_hook();
+    match catch_unwind(body) {
+        Ok(result) => result,
+        Err(_) => {
+            enter_fault_state(entry);
+            request_shutdown();
+            fallback
+        }
+    }
+}
+
+/// Marks the core as faulted. Core logic is not run again until the next
+/// retro_init(), and retro_run() shows an error screen instead.
+pub fn enter_fault_state(entry: &str)
+{
+    if !FAULTED.swap(true, Ordering::SeqCst) {
+        retro_log(LogLevel::LogError,
+                  &format!("Panic in {}, core halted.", entry));
+    }
+}
+
+pub fn is_faulted() -> bool
+{
+    FAULTED.load(Ordering::SeqCst)
+}
+
+pub fn clear_fault()
+{
+    FAULTED.store(false, Ordering::SeqCst);
+    SHUTDOWN_REQUESTED.store(false, Ordering::SeqCst);
+}
+
+/// Sends RETRO_ENVIRONMENT_SHUTDOWN once per fault. Must be called from the
+/// frontend's thread.
+pub fn request_shutdown()
+{
+    if !SHUTDOWN_REQUESTED.swap(true, Ordering::SeqCst) {
+        notify("The core has crashed. See the log for details.", 10.0);
+        environment(RETRO_ENVIRONMENT_SHUTDOWN, ptr::null_mut());
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn hook_chains_and_is_restored()
+    {
+        thread_local! {
+            // Per thread, so other tests' panics aren't counted
+            static HOST_PANICS: Cell<u32> = const { Cell::new(0) };
+        }
+        let test_hook = panic::take_hook();
+        panic::set_hook(Box::new(|_| HOST_PANICS.set(HOST_PANICS.get() + 1)));
+
+        install_panic_hook();
+        assert!(panic::catch_unwind(|| panic!("host")).is_err());
+        assert_eq!(HOST_PANICS.get(), 1);
+        assert!(catch_unwind(|| panic!("core")).is_err());
+        assert_eq!(HOST_PANICS.get(), 1);
+
+        uninstall_panic_hook();
+        assert!(catch_unwind(|| panic!("core after deinit")).is_err());
+        assert_eq!(HOST_PANICS.get(), 2);
+        panic::set_hook(test_hook);
+    }
+}
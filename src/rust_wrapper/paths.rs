@@ -0,0 +1,166 @@
+//! Directories and files provided by the frontend.
+//!
+//! The frontend's directories are asked for once when content is loaded, or
+//! on first use before then, and kept in Paths. Files are named relative to
+//! a directory, and names that would leave it, such as absolute paths or
+//! ones using "..", are refused. Save files are written atomically: to a
+//! temporary file first, which then replaces the old file, so a crash or
+//! full disk never leaves a half written save behind.
+
+use core::ffi::{c_char, c_uint, c_void};
+use std::ffi::CStr;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use super::environment;
+use super::libretro::*;
+
+/// The frontend's directories, and the paths of the core and its content.
+/// Any of them may be missing, as frontends needn't provide them.
+#[derive(Clone, Debug, Default)]
+pub struct Paths
+{
+    /// Where BIOS images and other files the core needs are kept.
+    pub system_dir: Option<PathBuf>,
+    /// Where the core should keep save files.
+    pub save_dir: Option<PathBuf>,
+    /// Where the core's own assets are installed.
+    pub content_dir: Option<PathBuf>,
+    /// The loaded content file.
+    pub content_path: Option<PathBuf>,
+    /// The core's library file.
+    pub libretro_path: Option<PathBuf>,
+}
+
+static PATHS: Mutex<Option<Paths>> = Mutex::new(None);
+
+fn lock() -> MutexGuard<'static, Option<Paths>>
+{
+    PATHS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Asks the frontend for a path, or None if it hasn't got one.
+fn get_path(cmd: c_uint) -> Option<PathBuf>
+{
+    let mut path: *const c_char = std::ptr::null();
+    if !environment(cmd, &mut path as *mut *const c_char as *mut c_void) || path.is_null() {
+        return None;
+    }
+    let path = unsafe { CStr::from_ptr(path) };
+    if path.is_empty() {
+        return None;
+    }
+    // Paths are UTF-8, but keep the exact bytes where the platform allows
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Some(PathBuf::from(std::ffi::OsStr::from_bytes(path.to_bytes())))
+    }
+    #[cfg(not(unix))]
+    {
+        Some(PathBuf::from(path.to_string_lossy().into_owned()))
+    }
+}
+
+fn query(content_path: Option<&str>) -> Paths
+{
+    Paths {
+        system_dir: get_path(RETRO_ENVIRONMENT_GET_SYSTEM_DIRECTORY),
+        save_dir: get_path(RETRO_ENVIRONMENT_GET_SAVE_DIRECTORY),
+        content_dir: get_path(RETRO_ENVIRONMENT_GET_CONTENT_DIRECTORY),
+        content_path: content_path.map(PathBuf::from),
+        libretro_path: get_path(RETRO_ENVIRONMENT_GET_LIBRETRO_PATH),
+    }
+}
+
+/// Asks the frontend for its directories again, as content is loaded.
+pub fn load(content_path: Option<&str>)
+{
+    let paths = query(content_path);
+    log_debug!("{:?}", paths);
+    *lock() = Some(paths);
+}
+
+/// The frontend's directories and the content's path.
+pub fn paths() -> Paths
+{
+    lock().get_or_insert_with(|| query(None)).clone()
+}
+
+/// Joins a file name to a directory, refusing names that leave it.
+fn file_in(dir: Option<PathBuf>, kind: &str, name: &str) -> io::Result<PathBuf>
+{
+    let dir = dir.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound,
+                                               format!("frontend has no {} directory", kind)))?;
+    let name = Path::new(name);
+    if name.as_os_str().is_empty() ||
+       !name.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("{} isn't a file name inside the {} directory",
+                                          name.display(), kind)));
+    }
+    Ok(dir.join(name))
+}
+
+/// Path of a file in the system directory.
+pub fn system_file(name: &str) -> io::Result<PathBuf>
+{
+    file_in(paths().system_dir, "system", name)
+}
+
+/// Path of a file in the save directory.
+pub fn save_file(name: &str) -> io::Result<PathBuf>
+{
+    file_in(paths().save_dir, "save", name)
+}
+
+/// A file name made from the core's name, so cores sharing a save directory
+/// keep apart, such as "Example_Core.movie" for the extension "movie".
+pub fn core_file_name(extension: &str) -> String
+{
+    use super::super::CORE_NAME;
+
+    let name: String = CORE_NAME.trim_end_matches('\0').chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{}.{}", name, extension)
+}
+
+/// Reads a BIOS image or other file from the system directory, for example
+/// read_system_file("example_bios.bin") in core_load_game().
+pub fn read_system_file(name: &str) -> io::Result<Vec<u8>>
+{
+    fs::read(system_file(name)?)
+}
+
+/// Reads a file written by write_save_file().
+pub fn read_save_file(name: &str) -> io::Result<Vec<u8>>
+{
+    fs::read(save_file(name)?)
+}
+
+/// Writes a file to the save directory, creating any subdirectories in its
+/// name. The file is either replaced whole or left as it was.
+pub fn write_save_file(name: &str, data: &[u8]) -> io::Result<()>
+{
+    let path = save_file(name)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut temp_name = path.file_name().unwrap().to_os_string();
+    temp_name.push(".tmp");
+    let temp = path.with_file_name(temp_name);
+
+    let result = (|| {
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&temp, &path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
@@ -0,0 +1,251 @@
+//! Performance counters.
+//!
+//! perf_scope!("physics") times the rest of the enclosing block. Each call
+//! site has its own counter, which is registered with the frontend's
+//! performance counters when it offers RETRO_ENVIRONMENT_GET_PERF_INTERFACE,
+//! so they show up in its own reports. Times are also kept for every frame,
+//! taken with the frontend's timer, or a local one if it has none, and the
+//! performance report core option logs them once the frame is done, along
+//! with the time spent in core_run(), snapshot_video() and render_video().
+//! The frontend's counters needn't be thread safe, so counters started on
+//! other threads than the frontend's, such as render_video()'s, are only
+//! timed locally.
+//!
+//! A counter times one thread at a time: render_video() and core_run() run
+//! together, so they shouldn't share a perf_scope! call site.
+
+use core::ffi::{c_char, c_void};
+use std::mem::MaybeUninit;
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread::{self, ThreadId};
+use std::time::Instant;
+
+use super::libretro::*;
+use super::log::{log_fmt, LogLevel};
+use super::{environment, get_variable, video_frame};
+
+const PERF_REPORT_KEY: &str = "perf_report\0";
+
+/// Times the rest of the enclosing block with a counter named name, for
+/// example perf_scope!("render").
+#[allow(unused_macros)]
+macro_rules! perf_scope(
+    ($name:expr) => (
+        let _perf_scope = {
+            static COUNTER: $crate::rust_wrapper::PerfCounter =
+                $crate::rust_wrapper::PerfCounter::new(concat!($name, "\0"));
+            COUNTER.start()
+        };
+    );
+);
+
+/// The parts of retro_perf_callback we use.
+#[derive(Clone, Copy)]
+struct Interface
+{
+    get_time_usec: retro_perf_get_time_usec_t,
+    get_cpu_features: retro_get_cpu_features_t,
+    perf_register: retro_perf_register_t,
+    perf_start: retro_perf_start_t,
+    perf_stop: retro_perf_stop_t,
+    perf_log: retro_perf_log_t,
+}
+
+/// The frontend's interface and the thread it may be used from.
+static INTERFACE: Mutex<Option<(Interface, ThreadId)>> = Mutex::new(None);
+static REPORT: AtomicBool = AtomicBool::new(false);
+/// Counters that have been started, in the order they first were.
+static COUNTERS: Mutex<Vec<&'static PerfCounter>> = Mutex::new(Vec::new());
+
+/// The frontend's interface, if it has one and this is the frontend's
+/// thread.
+fn interface() -> Option<Interface>
+{
+    INTERFACE.lock().unwrap_or_else(PoisonError::into_inner)
+        .filter(|&(_, frontend_thread)| frontend_thread == thread::current().id())
+        .map(|(interface, _)| interface)
+}
+
+fn counters() -> MutexGuard<'static, Vec<&'static PerfCounter>>
+{
+    COUNTERS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Asks the frontend for its performance counters, in
+/// retro_set_environment().
+pub fn init()
+{
+    let mut callback = MaybeUninit::<retro_perf_callback>::uninit();
+    let interface = environment(RETRO_ENVIRONMENT_GET_PERF_INTERFACE,
+                                callback.as_mut_ptr() as *mut c_void)
+        .then(|| {
+            let callback = unsafe { callback.assume_init() };
+            Interface {
+                get_time_usec: callback.get_time_usec,
+                get_cpu_features: callback.get_cpu_features,
+                perf_register: callback.perf_register,
+                perf_start: callback.perf_start,
+                perf_stop: callback.perf_stop,
+                perf_log: callback.perf_log,
+            }
+        });
+    if interface.is_none() {
+        log_debug!("Frontend has no performance counters, timing locally");
+    }
+    *INTERFACE.lock().unwrap_or_else(PoisonError::into_inner) =
+        interface.map(|interface| (interface, thread::current().id()));
+}
+
+/// The CPU features the frontend detected, as RETRO_SIMD_* flags, or 0 if it
+/// can't tell.
+pub fn cpu_features() -> u64
+{
+    interface().map_or(0, |interface| (interface.get_cpu_features)())
+}
+
+/// Microseconds from the frontend's timer, or from a local one.
+fn now_usec(interface: Option<Interface>) -> u64
+{
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+    match interface {
+        Some(interface) => (interface.get_time_usec)() as u64,
+        None => EPOCH.get_or_init(Instant::now).elapsed().as_micros() as u64,
+    }
+}
+
+/// A frontend counter, which holds a pointer to its name.
+struct FrontendCounter(retro_perf_counter);
+
+// The name is a &'static str
+unsafe impl Send for FrontendCounter {}
+
+/// A performance counter, usually made by perf_scope!.
+pub struct PerfCounter
+{
+    /// Null terminated.
+    name: &'static str,
+    frontend: Mutex<FrontendCounter>,
+    listed: AtomicBool,
+    frame_usec: AtomicU64,
+    frame_calls: AtomicU64,
+}
+
+impl PerfCounter
+{
+    /// A counter called name, which must end with a null.
+    pub const fn new(name: &'static str) -> PerfCounter
+    {
+        PerfCounter {
+            name,
+            frontend: Mutex::new(FrontendCounter(retro_perf_counter {
+                ident: name.as_ptr() as *const c_char,
+                start: 0,
+                total: 0,
+                call_cnt: 0,
+                registered: 0,
+            })),
+            listed: AtomicBool::new(false),
+            frame_usec: AtomicU64::new(0),
+            frame_calls: AtomicU64::new(0),
+        }
+    }
+
+    fn name(&self) -> &'static str
+    {
+        self.name.trim_end_matches('\0')
+    }
+
+    /// Starts timing, until the PerfScope is dropped.
+    pub fn start(&'static self) -> PerfScope
+    {
+        if !self.listed.swap(true, Ordering::Relaxed) {
+            counters().push(self);
+        }
+        let interface = interface();
+        if let Some(interface) = interface {
+            let mut frontend = self.frontend.lock().unwrap_or_else(PoisonError::into_inner);
+            // The counter is a static, so it stays where the frontend saw it
+            if frontend.0.registered == 0 {
+                (interface.perf_register)(&mut frontend.0);
+            }
+            (interface.perf_start)(&mut frontend.0);
+        }
+        PerfScope { counter: self, interface, start: now_usec(interface) }
+    }
+}
+
+/// Times a PerfCounter while it lives.
+pub struct PerfScope
+{
+    counter: &'static PerfCounter,
+    interface: Option<Interface>,
+    start: u64,
+}
+
+impl Drop for PerfScope
+{
+    fn drop(&mut self)
+    {
+        let elapsed = now_usec(self.interface).saturating_sub(self.start);
+        if let Some(interface) = self.interface {
+            let mut frontend = self.counter.frontend.lock().unwrap_or_else(PoisonError::into_inner);
+            (interface.perf_stop)(&mut frontend.0);
+        }
+        self.counter.frame_usec.fetch_add(elapsed, Ordering::Relaxed);
+        self.counter.frame_calls.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Builds the performance report option as null terminated key and value
+/// strings for RETRO_ENVIRONMENT_SET_VARIABLES.
+pub fn variable() -> (String, String)
+{
+    (PERF_REPORT_KEY.to_string(), "Performance report; off|on\0".to_string())
+}
+
+/// Reads the performance report option. Called whenever the frontend reports
+/// a core option change.
+pub fn load_options()
+{
+    REPORT.store(get_variable(PERF_REPORT_KEY).as_deref() == Some("on"), Ordering::Relaxed);
+}
+
+/// Logs the frame's times if the performance report option is on, once
+/// render_video() has finished, and starts timing the next frame.
+pub fn end_frame()
+{
+    let enabled = REPORT.load(Ordering::Relaxed);
+    let mut report = String::new();
+    for counter in counters().iter() {
+        let usec = counter.frame_usec.swap(0, Ordering::Relaxed);
+        let calls = counter.frame_calls.swap(0, Ordering::Relaxed);
+        if !enabled || calls == 0 {
+            continue;
+        }
+        if !report.is_empty() {
+            report.push_str(", ");
+        }
+        report.push_str(&format!("{} {}us", counter.name(), usec));
+        if calls > 1 {
+            report.push_str(&format!(" ({} calls)", calls));
+        }
+    }
+    // The option asks for every frame, so this isn't rate limited
+    if !report.is_empty() {
+        log_fmt(LogLevel::LogInfo, module_path!(), 0,
+                format_args!("Frame {}: {}", video_frame(), report));
+    }
+}
+
+/// Asks the frontend to log its totals for the counters, when the core is
+/// shut down.
+pub fn log_totals()
+{
+    if let Some(interface) = interface() {
+        if !counters().is_empty() {
+            (interface.perf_log)();
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! The region the content runs in, NTSC or PAL.
+//!
+//! REGION says whether the core always runs in one region, follows the
+//! content, or lets the player choose with a core option. The region is
+//! settled when content is loaded, once core_load_game() has had the chance
+//! to read the content it is passed and report the region with set_region().
+//! PAL content runs its logic at PAL_CORE_LOGIC_RATE rather than
+//! CORE_LOGIC_RATE, and the frame rate falls back to 50fps rather than 60fps
+//! when the frontend doesn't say what it is targeting.
+
+use core::ffi::c_uint;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::get_variable;
+use super::libretro::*;
+
+const REGION_KEY: &str = "region\0";
+
+/// A television standard, and so a frame rate and game speed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Region
+{
+    /// 60Hz, logic at CORE_LOGIC_RATE.
+    Ntsc = RETRO_REGION_NTSC as isize,
+    /// 50Hz, logic at PAL_CORE_LOGIC_RATE.
+    Pal = RETRO_REGION_PAL as isize,
+}
+
+impl Region
+{
+    /// Logic updates per second in this region.
+    pub fn core_logic_rate(self) -> u32
+    {
+        use super::super::{CORE_LOGIC_RATE, PAL_CORE_LOGIC_RATE};
+
+        match self {
+            Region::Ntsc => CORE_LOGIC_RATE,
+            Region::Pal => PAL_CORE_LOGIC_RATE,
+        }
+    }
+
+    /// Frame rate of the region's televisions.
+    pub fn fps(self) -> u32
+    {
+        match self {
+            Region::Ntsc => 60,
+            Region::Pal => 50,
+        }
+    }
+}
+
+/// Where the region comes from, set by REGION.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RegionSource
+{
+    /// Always this region.
+    Fixed(Region),
+    /// The region core_load_game() passes to set_region(), or NTSC if it
+    /// doesn't.
+    Content,
+    /// A core option, defaulting to the region of the content as with
+    /// Content.
+    Option,
+}
+
+const _: () = assert!(super::super::PAL_CORE_LOGIC_RATE.is_multiple_of(50));
+
+static PAL: AtomicBool = AtomicBool::new(matches!(super::super::REGION,
+                                                  RegionSource::Fixed(Region::Pal)));
+static CONTENT_PAL: AtomicBool = AtomicBool::new(false);
+/// Set when loading content changes the region.
+static CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// Builds the region option as null terminated key and value strings for
+/// RETRO_ENVIRONMENT_SET_VARIABLES, if REGION asks for one.
+pub fn variable() -> Option<(String, String)>
+{
+    (super::super::REGION == RegionSource::Option)
+        .then(|| (REGION_KEY.to_string(), "Region (restart); auto|NTSC|PAL\0".to_string()))
+}
+
+/// Reports the region of the content being loaded. Call from
+/// core_load_game(), for example after reading the header in content.data. Has no
+/// effect when REGION is fixed, or when the region option is set.
+pub fn set_region(region: Region)
+{
+    if let RegionSource::Fixed(_) = super::super::REGION {
+        log_warn!("set_region() called with a fixed REGION");
+    }
+    CONTENT_PAL.store(region == Region::Pal, Ordering::Relaxed);
+}
+
+/// Forgets the content's region, before core_load_game().
+pub fn clear()
+{
+    CONTENT_PAL.store(false, Ordering::Relaxed);
+}
+
+/// Settles the region once core_load_game() has returned.
+pub fn load()
+{
+    let region = match super::super::REGION {
+        RegionSource::Fixed(region) => region,
+        RegionSource::Content => content_region(),
+        RegionSource::Option => match get_variable(REGION_KEY).as_deref() {
+            Some("NTSC") => Region::Ntsc,
+            Some("PAL") => Region::Pal,
+            _ => content_region(),
+        },
+    };
+    if PAL.swap(region == Region::Pal, Ordering::Relaxed) != (region == Region::Pal) {
+        CHANGED.store(true, Ordering::Relaxed);
+    }
+    log_info!("{} region, {} logic updates per second",
+              if region == Region::Pal { "PAL" } else { "NTSC" }, region.core_logic_rate());
+}
+
+fn content_region() -> Region
+{
+    if CONTENT_PAL.load(Ordering::Relaxed) { Region::Pal } else { Region::Ntsc }
+}
+
+/// Whether loading content changed the region since the last call, so the
+/// frame rate needs choosing again.
+pub fn take_changed() -> bool
+{
+    CHANGED.swap(false, Ordering::Relaxed)
+}
+
+/// The region the content runs in.
+pub fn region() -> Region
+{
+    if PAL.load(Ordering::Relaxed) { Region::Pal } else { Region::Ntsc }
+}
+
+/// Logic updates per second for the current region.
+pub fn core_logic_rate() -> u32
+{
+    region().core_logic_rate()
+}
+
+/// Logic rates the content may run at, for building the frame rate option
+/// before the region is known.
+pub fn possible_logic_rates() -> Vec<u32>
+{
+    match super::super::REGION {
+        RegionSource::Fixed(region) => vec![region.core_logic_rate()],
+        _ => vec![Region::Ntsc.core_logic_rate(), Region::Pal.core_logic_rate()],
+    }
+}
+
+/// retro_get_region().
+pub fn retro_region() -> c_uint
+{
+    region() as c_uint
+}
@@ -0,0 +1,322 @@
+//! Rewind.
+//!
+//! While rewind is enabled, the core state is saved after every few logic
+//! updates. Holding the rewind button on the first controller steps back
+//! through the saved states, one per video frame, instead of running the
+//! core. The newest state is kept whole and each older one as the bytes that
+//! differ from the state after it, run length encoded, so the buffer holds
+//! several minutes of a small core. The oldest states are dropped to keep
+//! within the buffer size option.
+//!
+//! Rewind is off while an input recording is recording or playing back, as
+//! rewinding would break the recording.
+
+use core::ffi::c_uint;
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use super::input::{self, ControllerButton};
+use super::input::ControllerButton::*;
+use super::libretro::RETRO_DEVICE_JOYPAD;
+use super::{get_variable, movie, retro_input_state_cb, serialize};
+
+const REWIND_KEY: &str = "rewind\0";
+const REWIND_BUTTON_KEY: &str = "rewind_button\0";
+const REWIND_INTERVAL_KEY: &str = "rewind_interval\0";
+const REWIND_BUFFER_KEY: &str = "rewind_buffer\0";
+
+const REWIND_BUTTONS: &[(&str, ControllerButton)] = &[("L2", PadL2),
+                                                      ("R2", PadR2),
+                                                      ("L3", PadL3),
+                                                      ("R3", PadR3),
+                                                      ("L", PadL),
+                                                      ("R", PadR),
+                                                      ("Select", PadSelect)];
+/// Logic updates between saved states offered, besides one video frame at
+/// 60fps, the default.
+const REWIND_INTERVALS: &[u64] = &[1, 2, 4, 8, 16, 32, 64];
+/// Buffer sizes offered in megabytes, the default first.
+const REWIND_BUFFER_MB: &[usize] = &[32, 8, 16, 64, 128, 256];
+
+struct Rewind
+{
+    enabled: bool,
+    button: ControllerButton,
+    interval: u64,
+    budget: usize,
+    /// The newest saved state, or empty if there is none.
+    current: Vec<u8>,
+    /// Deltas, newest at the back, each turning the state after it into the
+    /// one before.
+    deltas: VecDeque<Vec<u8>>,
+    delta_bytes: usize,
+}
+
+static REWIND: Mutex<Rewind> = Mutex::new(Rewind {
+    enabled: false,
+    button: PadL2,
+    interval: 1,
+    budget: 0,
+    current: Vec::new(),
+    deltas: VecDeque::new(),
+    delta_bytes: 0,
+});
+
+fn lock() -> MutexGuard<'static, Rewind>
+{
+    REWIND.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn default_interval() -> u64
+{
+    use super::super::CORE_LOGIC_RATE;
+
+    (CORE_LOGIC_RATE as u64 / 60).max(1)
+}
+
+/// Builds the rewind options as null terminated key and value strings for
+/// RETRO_ENVIRONMENT_SET_VARIABLES.
+pub fn variables() -> Vec<(String, String)>
+{
+    let buttons: Vec<&str> = REWIND_BUTTONS.iter().map(|&(name, _)| name).collect();
+    let mut intervals = vec![default_interval()];
+    intervals.extend(REWIND_INTERVALS.iter().filter(|&&i| i != default_interval()));
+    let intervals: Vec<String> = intervals.iter().map(u64::to_string).collect();
+    let buffers: Vec<String> = REWIND_BUFFER_MB.iter().map(|mb| format!("{} MB", mb)).collect();
+
+    vec![(REWIND_KEY.to_string(), "Rewind; off|on\0".to_string()),
+         (REWIND_BUTTON_KEY.to_string(), format!("Rewind button; {}\0", buttons.join("|"))),
+         (REWIND_INTERVAL_KEY.to_string(),
+          format!("Rewind interval in logic updates; {}\0", intervals.join("|"))),
+         (REWIND_BUFFER_KEY.to_string(),
+          format!("Rewind buffer size; {}\0", buffers.join("|")))]
+}
+
+/// Reads the rewind options. Called whenever the frontend reports a core
+/// option change. Changing the interval or buffer size discards the saved
+/// states.
+pub fn load_options()
+{
+    let enabled = get_variable(REWIND_KEY).as_deref() == Some("on");
+    let button = get_variable(REWIND_BUTTON_KEY)
+        .and_then(|value| REWIND_BUTTONS.iter().find(|&&(name, _)| name == value))
+        .map_or(PadL2, |&(_, button)| button);
+    let interval = get_variable(REWIND_INTERVAL_KEY)
+        .and_then(|value| value.parse().ok())
+        .filter(|&interval| interval > 0)
+        .unwrap_or_else(default_interval);
+    let budget = get_variable(REWIND_BUFFER_KEY)
+        .and_then(|value| value.trim_end_matches(" MB").parse().ok())
+        .unwrap_or(REWIND_BUFFER_MB[0]) << 20;
+
+    let mut rewind = lock();
+    if (enabled, interval, budget) != (rewind.enabled, rewind.interval, rewind.budget) {
+        discard(&mut rewind);
+    }
+    rewind.enabled = enabled;
+    rewind.button = button;
+    rewind.interval = interval;
+    rewind.budget = budget;
+}
+
+/// Discards the saved states, when the core state jumps for a reset or a
+/// loaded save state.
+pub fn clear()
+{
+    discard(&mut lock());
+}
+
+fn discard(rewind: &mut Rewind)
+{
+    rewind.current = Vec::new();
+    rewind.deltas.clear();
+    rewind.delta_bytes = 0;
+}
+
+/// Saves the state if it's due. Called after every logic update.
+pub fn after_tick()
+{
+    let mut rewind = lock();
+    if !rewind.enabled || movie::is_active() ||
+       !input::tick_time().tick.is_multiple_of(rewind.interval) {
+        return;
+    }
+
+    let state = serialize::save_vec();
+    if !rewind.current.is_empty() {
+        let delta = encode_delta(&rewind.current, &state);
+        rewind.delta_bytes += delta.len();
+        rewind.deltas.push_back(delta);
+    }
+    rewind.current = state;
+
+    while rewind.current.len() + rewind.delta_bytes > rewind.budget {
+        match rewind.deltas.pop_front() {
+            Some(delta) => rewind.delta_bytes -= delta.len(),
+            None => break,
+        }
+    }
+}
+
+/// Steps back one saved state if the rewind button is held. Returns whether
+/// the core was rewound, in which case no logic updates should run this
+/// frame.
+pub fn rewind_frame() -> bool
+{
+    let mut rewind = lock();
+    if !rewind.enabled || rewind.current.is_empty() || movie::is_active() {
+        return false;
+    }
+    let input_state = unsafe { retro_input_state_cb.unwrap() };
+    if input_state(0, RETRO_DEVICE_JOYPAD, 0, rewind.button as c_uint) == 0 {
+        return false;
+    }
+
+    // The newest state stays when there are no older ones, so holding the
+    // button at the start of the buffer holds the oldest state
+    if let Some(delta) = rewind.deltas.pop_back() {
+        rewind.delta_bytes -= delta.len();
+        apply_delta(&mut rewind.current, &delta);
+    }
+    serialize::load(&rewind.current)
+}
+
+/// Encodes the bytes of `to` that differ from `from`, as pairs of run
+/// lengths of unchanged and changed bytes, each changed run followed by the
+/// changed bytes XORed with the originals. The same delta turns either state
+/// into the other.
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8>
+{
+    let mut delta = Vec::new();
+    let mut i = 0;
+    while i < from.len() {
+        let unchanged = i;
+        while i < from.len() && from[i] == to[i] {
+            i += 1;
+        }
+        let changed = i;
+        while i < from.len() && from[i] != to[i] {
+            i += 1;
+        }
+        if changed == from.len() {
+            break;
+        }
+        write_varint(&mut delta, changed - unchanged);
+        write_varint(&mut delta, i - changed);
+        delta.extend(from[changed..i].iter().zip(&to[changed..i]).map(|(a, b)| a ^ b));
+    }
+    delta
+}
+
+fn apply_delta(state: &mut [u8], delta: &[u8])
+{
+    let mut pos = 0;
+    let mut at = 0;
+    while at < delta.len() {
+        pos += read_varint(delta, &mut at);
+        let len = read_varint(delta, &mut at);
+        for (byte, change) in state[pos..pos + len].iter_mut().zip(&delta[at..at + len]) {
+            *byte ^= change;
+        }
+        pos += len;
+        at += len;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize)
+{
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], at: &mut usize) -> usize
+{
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*at];
+        *at += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Checks the delta turns each state into the other, returning it.
+    fn round_trip(from: &[u8], to: &[u8]) -> Vec<u8>
+    {
+        let delta = encode_delta(from, to);
+        let mut state = from.to_vec();
+        apply_delta(&mut state, &delta);
+        assert_eq!(state, to);
+        apply_delta(&mut state, &delta);
+        assert_eq!(state, from);
+        delta
+    }
+
+    #[test]
+    fn identical_states_have_an_empty_delta()
+    {
+        let state: Vec<u8> = (0..=255).collect();
+        assert!(round_trip(&state, &state).is_empty());
+        assert!(round_trip(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn fully_different_states()
+    {
+        let from = vec![0x55; 300];
+        let to = vec![0xaa; 300];
+        let delta = round_trip(&from, &to);
+        // No unchanged run, one changed run of 300 bytes, then the bytes
+        assert_eq!(&delta[..3], &[0, 0xac, 0x02]);
+        assert_eq!(delta.len(), 3 + 300);
+        assert!(delta[3..].iter().all(|&byte| byte == 0xff));
+    }
+
+    #[test]
+    fn runs_at_varint_boundaries()
+    {
+        for len in [1, 127, 128, 16383, 16384, 70000] {
+            let from = vec![0; len * 2 + 1];
+            let mut to = from.clone();
+            // An unchanged run of len, then a changed run of len
+            to[len..len * 2].fill(1);
+            let delta = round_trip(&from, &to);
+            let mut at = 0;
+            assert_eq!(read_varint(&delta, &mut at), len);
+            assert_eq!(read_varint(&delta, &mut at), len);
+            assert_eq!(delta.len(), at + len);
+        }
+    }
+
+    #[test]
+    fn trailing_changed_run()
+    {
+        let from = vec![7; 10];
+        let mut to = from.clone();
+        to[2] = 0;
+        to[8..].fill(9);
+        let delta = round_trip(&from, &to);
+        assert_eq!(delta, [2, 1, 7, 5, 2, 14, 14]);
+    }
+
+    #[test]
+    fn trailing_unchanged_run_is_left_out()
+    {
+        let from = vec![7; 10];
+        let mut to = from.clone();
+        to[0] = 6;
+        assert_eq!(round_trip(&from, &to), [0, 1, 1]);
+    }
+}
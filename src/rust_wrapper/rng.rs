@@ -0,0 +1,293 @@
+//! Deterministic random numbers.
+//!
+//! rng() is a PCG32 generator for core logic. Its state is saved in the save
+//! state header, so save states, rewind, input recordings and the determinism
+//! check all see the same numbers. It is seeded with RANDOM_SEED when content
+//! is loaded and again on every reset, or with the seed last passed to
+//! seed_rng(). Nothing here reads the clock or any other outside source.
+//!
+//! Cores wanting separate streams, for example so that cosmetic effects don't
+//! change the numbers gameplay sees, can keep their own Rng in their state.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::fixed::{Angle, Fixed};
+use super::serialize::{StateReader, StateWriter};
+
+const MULTIPLIER: u64 = 6364136223846793005;
+const DEFAULT_STREAM: u64 = 0xda3e_39cb_94b9_5bdb;
+
+/// A PCG32 random number generator.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rng
+{
+    state: u64,
+    inc: u64,
+}
+
+impl Rng
+{
+    pub const fn new(seed: u64) -> Rng
+    {
+        Rng::with_stream(seed, DEFAULT_STREAM)
+    }
+
+    /// Generators with the same seed and different streams give unrelated
+    /// numbers.
+    pub const fn with_stream(seed: u64, stream: u64) -> Rng
+    {
+        let mut rng = Rng { state: 0, inc: stream << 1 | 1 };
+        rng.step();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.step();
+        rng
+    }
+
+    const fn step(&mut self)
+    {
+        self.state = self.state.wrapping_mul(MULTIPLIER).wrapping_add(self.inc);
+    }
+
+    pub fn next_u32(&mut self) -> u32
+    {
+        let old = self.state;
+        self.step();
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    pub fn next_u64(&mut self) -> u64
+    {
+        (self.next_u32() as u64) << 32 | self.next_u32() as u64
+    }
+
+    /// A number from 0 to n - 1, every one equally likely. Panics if n is 0.
+    pub fn below(&mut self, n: u32) -> u32
+    {
+        assert!(n > 0, "Rng::below(0)");
+        // Lemire's method, rejecting the few products that would bias the
+        // result
+        let mut product = self.next_u32() as u64 * n as u64;
+        if (product as u32) < n {
+            let threshold = n.wrapping_neg() % n;
+            while (product as u32) < threshold {
+                product = self.next_u32() as u64 * n as u64;
+            }
+        }
+        (product >> 32) as u32
+    }
+
+    /// A number from low up to but not including high. Panics if high is not
+    /// greater than low.
+    pub fn range(&mut self, low: i32, high: i32) -> i32
+    {
+        assert!(high > low, "Rng::range({}, {}) is empty", low, high);
+        low.wrapping_add(self.below(high.wrapping_sub(low) as u32) as i32)
+    }
+
+    /// True with probability numerator / denominator.
+    pub fn chance(&mut self, numerator: u32, denominator: u32) -> bool
+    {
+        self.below(denominator) < numerator
+    }
+
+    /// A fixed point number from 0 up to but not including 1.
+    pub fn fixed<const FRAC: u32>(&mut self) -> Fixed<FRAC>
+    {
+        Fixed::from_raw(self.next_u32().checked_shr(32 - FRAC).unwrap_or(0) as i32)
+    }
+
+    pub fn angle(&mut self) -> Angle
+    {
+        Angle((self.next_u32() >> 16) as u16)
+    }
+
+    pub fn serialize(&self, state: &mut StateWriter)
+    {
+        state.write_u64(self.state);
+        state.write_u64(self.inc);
+    }
+
+    pub fn unserialize(state: &mut StateReader) -> Rng
+    {
+        let rng_state = state.read_u64();
+        Rng { state: rng_state, inc: state.read_u64() | 1 }
+    }
+}
+
+static SEED: AtomicU64 = AtomicU64::new(super::super::RANDOM_SEED);
+static STATE: AtomicU64 = AtomicU64::new(Rng::new(super::super::RANDOM_SEED).state);
+static INC: AtomicU64 = AtomicU64::new(Rng::new(super::super::RANDOM_SEED).inc);
+
+fn load() -> Rng
+{
+    Rng { state: STATE.load(Ordering::Relaxed), inc: INC.load(Ordering::Relaxed) }
+}
+
+fn store(rng: Rng)
+{
+    STATE.store(rng.state, Ordering::Relaxed);
+    INC.store(rng.inc, Ordering::Relaxed);
+}
+
+/// Runs f on the core's generator and keeps the new state.
+fn update<R>(f: impl FnOnce(&mut Rng) -> R) -> R
+{
+    let mut rng = load();
+    let result = f(&mut rng);
+    store(rng);
+    result
+}
+
+/// The core's random number generator, for use in core_run(). For example
+/// rng().range(1, 7) rolls a die. Every call updates the generator before it
+/// returns, so calls can be nested, as in rng().range(1, rng().below(3) + 2).
+pub fn rng() -> CoreRng
+{
+    CoreRng(())
+}
+
+/// The core's random number generator, returned by rng(). See Rng for the
+/// methods.
+#[derive(Clone, Copy, Debug)]
+pub struct CoreRng(());
+
+impl CoreRng
+{
+    pub fn next_u32(self) -> u32 { update(Rng::next_u32) }
+    pub fn next_u64(self) -> u64 { update(Rng::next_u64) }
+    pub fn below(self, n: u32) -> u32 { update(|rng| rng.below(n)) }
+    pub fn range(self, low: i32, high: i32) -> i32 { update(|rng| rng.range(low, high)) }
+    pub fn chance(self, numerator: u32, denominator: u32) -> bool
+    {
+        update(|rng| rng.chance(numerator, denominator))
+    }
+    pub fn fixed<const FRAC: u32>(self) -> Fixed<FRAC> { update(Rng::fixed) }
+    pub fn angle(self) -> Angle { update(Rng::angle) }
+
+    /// A copy of the generator as it is now.
+    pub fn get(self) -> Rng
+    {
+        load()
+    }
+
+    /// Replaces the generator, for example with one from Rng::unserialize().
+    pub fn set(self, rng: Rng)
+    {
+        store(rng);
+    }
+}
+
+/// Seeds rng(), and makes later resets seed it the same way.
+pub fn seed_rng(seed: u64)
+{
+    SEED.store(seed, Ordering::Relaxed);
+    store(Rng::new(seed));
+}
+
+/// Seeds rng() again with its last seed, for a reset or newly loaded content.
+pub fn reseed()
+{
+    store(Rng::new(SEED.load(Ordering::Relaxed)));
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn matches_the_pcg32_reference()
+    {
+        // pcg32-demo's output for pcg32_srandom_r(42, 54)
+        let mut rng = Rng::with_stream(42, 54);
+        let expected = [0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e];
+        for value in expected {
+            assert_eq!(rng.next_u32(), value);
+        }
+    }
+
+    #[test]
+    fn below_stays_in_bounds()
+    {
+        let mut rng = Rng::new(1);
+        for n in [1, 2, 3, 7, 1000, 0x8000_0000, 0x8000_0001, u32::MAX] {
+            for _ in 0..1000 {
+                assert!(rng.below(n) < n);
+            }
+        }
+        let mut seen = [false; 6];
+        for _ in 0..1000 {
+            seen[rng.below(6) as usize] = true;
+        }
+        assert!(seen.iter().all(|&seen| seen));
+    }
+
+    #[test]
+    fn below_rejects_biased_draws()
+    {
+        // Nearly half of all draws are rejected for this n
+        let n: u32 = 0x8000_0001;
+        let threshold = n.wrapping_neg() % n;
+        let mut rng = Rng::new(7);
+        let mut draws = rng;
+        let mut rejected = 0;
+        for _ in 0..100 {
+            let value = rng.below(n);
+            let product = loop {
+                let product = draws.next_u32() as u64 * n as u64;
+                if product as u32 >= threshold {
+                    break product;
+                }
+                rejected += 1;
+            };
+            assert_eq!(value as u64, product >> 32);
+            assert_eq!(draws, rng);
+        }
+        assert!(rejected > 0);
+    }
+
+    #[test]
+    fn range_stays_in_bounds()
+    {
+        let mut rng = Rng::new(3);
+        for (low, high) in [(0, 1), (-3, 3), (1, 7), (i32::MIN, i32::MAX), (i32::MAX - 1, i32::MAX)] {
+            for _ in 0..1000 {
+                let value = rng.range(low, high);
+                assert!(low <= value && value < high, "{} not in {}..{}", value, low, high);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn empty_range_panics()
+    {
+        Rng::new(0).range(5, 5);
+    }
+
+    #[test]
+    fn serialize_round_trip()
+    {
+        let mut rng = Rng::with_stream(99, 12);
+        rng.next_u64();
+        let mut data = [0; 16];
+        rng.serialize(&mut StateWriter::new(&mut data));
+        let mut copy = Rng::unserialize(&mut StateReader::new(&data));
+        assert_eq!(copy, rng);
+        for _ in 0..10 {
+            assert_eq!(copy.next_u32(), rng.next_u32());
+        }
+    }
+
+    #[test]
+    fn nested_calls()
+    {
+        seed_rng(5);
+        let mut expected = Rng::new(5);
+        let inner = expected.below(3);
+        let outer = expected.range(1, inner as i32 + 2);
+        assert_eq!(rng().range(1, rng().below(3) as i32 + 2), outer);
+        assert_eq!(rng().get(), expected);
+    }
+}
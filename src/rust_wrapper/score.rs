@@ -0,0 +1,206 @@
+//! High score tables kept in the frontend's save directory.
+//!
+//! A table is loaded by name, such as "arcade", from a file named after the
+//! core and the table, and saved again whenever a score is added. Names are
+//! sanitized as with sanitize_name(), and scores made while cheats are active
+//! are never added, so the table only shows fair play.
+
+use super::cheat::cheats_active;
+use super::paths;
+use super::user::sanitize_name;
+
+const SCORES_MAGIC: &[u8; 8] = b"RLSCORE\0";
+const SCORES_VERSION: u32 = 1;
+const HEADER_LEN: usize = 16;
+
+/// A score and the name of the player who made it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HighScore
+{
+    pub name: String,
+    pub score: u64,
+}
+
+/// The best scores, highest first, at most capacity of them.
+#[derive(Clone, Debug)]
+pub struct HighScores
+{
+    file: String,
+    capacity: usize,
+    entries: Vec<HighScore>,
+}
+
+impl HighScores
+{
+    /// Loads the table called name, keeping at most capacity scores. A table
+    /// that has never been saved, or can't be read, starts empty.
+    pub fn load(name: &str, capacity: usize) -> HighScores
+    {
+        let table: String = name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let file = paths::core_file_name(&format!("{}.scores", table));
+        let entries = match paths::read_save_file(&file) {
+            Ok(data) => parse(&data).unwrap_or_else(|reason| {
+                log_error!("High scores in {} not loaded: {}", file, reason);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        let mut scores = HighScores { file, capacity, entries };
+        scores.entries.truncate(capacity);
+        scores
+    }
+
+    /// The scores, highest first.
+    pub fn entries(&self) -> &[HighScore]
+    {
+        &self.entries
+    }
+
+    /// Would score make the table? Ask before letting the player enter a
+    /// name.
+    pub fn qualifies(&self, score: u64) -> bool
+    {
+        !cheats_active() && self.rank(score) < self.capacity
+    }
+
+    /// Where score would go, after any equal scores already in the table.
+    fn rank(&self, score: u64) -> usize
+    {
+        self.entries.iter().take_while(|entry| entry.score >= score).count()
+    }
+
+    /// Adds a score and saves the table, returning its place from 0, or None
+    /// if it doesn't make the table or cheats are active. Use username() as
+    /// the name the player starts from.
+    pub fn add(&mut self, name: &str, score: u64) -> Option<usize>
+    {
+        if cheats_active() {
+            log_info!("Score of {} left out of the high scores, as cheats are active", score);
+            return None;
+        }
+        let rank = self.rank(score);
+        if rank >= self.capacity {
+            return None;
+        }
+        self.entries.insert(rank, HighScore { name: sanitize_name(name), score });
+        self.entries.truncate(self.capacity);
+        if let Err(error) = paths::write_save_file(&self.file, &serialize(&self.entries)) {
+            log_error!("Can't save {}: {}", self.file, error);
+        }
+        Some(rank)
+    }
+}
+
+fn serialize(entries: &[HighScore]) -> Vec<u8>
+{
+    let mut data = Vec::new();
+    data.extend_from_slice(SCORES_MAGIC);
+    data.extend_from_slice(&SCORES_VERSION.to_le_bytes());
+    data.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    for entry in entries {
+        data.extend_from_slice(&entry.score.to_le_bytes());
+        data.push(entry.name.len() as u8);
+        data.extend_from_slice(entry.name.as_bytes());
+    }
+    data
+}
+
+fn parse(data: &[u8]) -> Result<Vec<HighScore>, String>
+{
+    let u32_at = |at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+
+    if data.len() < HEADER_LEN || &data[..8] != SCORES_MAGIC {
+        return Err("not a high score table".to_string());
+    }
+    let version = u32_at(8);
+    if version != SCORES_VERSION {
+        return Err(format!("unsupported version {}", version));
+    }
+    let count = u32_at(12) as usize;
+    let mut entries = Vec::new();
+    let mut rest = &data[HEADER_LEN..];
+    for _ in 0..count {
+        if rest.len() < 9 || rest.len() < 9 + rest[8] as usize {
+            return Err("truncated".to_string());
+        }
+        let score = u64::from_le_bytes(rest[..8].try_into().unwrap());
+        let (name, next) = rest[9..].split_at(rest[8] as usize);
+        entries.push(HighScore { name: sanitize_name(&String::from_utf8_lossy(name)), score });
+        rest = next;
+    }
+    if !rest.is_empty() {
+        return Err("wrong length".to_string());
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn entries() -> Vec<HighScore>
+    {
+        vec![HighScore { name: "Ada".to_string(), score: u64::MAX },
+             HighScore { name: "Grace Hopper".to_string(), score: 500 },
+             HighScore { name: String::new(), score: 0 }]
+    }
+
+    #[test]
+    fn round_trip()
+    {
+        assert_eq!(parse(&serialize(&entries())).unwrap(), entries());
+        assert!(parse(&serialize(&[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn names_are_sanitized()
+    {
+        let mut data = serialize(&[]);
+        data[12] = 1;
+        data.extend_from_slice(&7u64.to_le_bytes());
+        let name = b"a\nb\xff  c";
+        data.push(name.len() as u8);
+        data.extend_from_slice(name);
+        assert_eq!(parse(&data).unwrap(), [HighScore { name: "a b\u{fffd} c".to_string(), score: 7 }]);
+    }
+
+    #[test]
+    fn truncated_files()
+    {
+        let data = serialize(&entries());
+        for len in [0, 7, HEADER_LEN - 1, HEADER_LEN, HEADER_LEN + 8, HEADER_LEN + 9, data.len() - 1] {
+            assert!(parse(&data[..len]).is_err(), "{} bytes accepted", len);
+        }
+        assert_eq!(parse(&data[..data.len() - 10]).unwrap_err(), "truncated");
+    }
+
+    #[test]
+    fn corrupt_files()
+    {
+        let data = serialize(&entries());
+
+        let mut bad_magic = data.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(parse(&bad_magic).unwrap_err(), "not a high score table");
+
+        let mut bad_version = data.clone();
+        bad_version[8] = 2;
+        assert_eq!(parse(&bad_version).unwrap_err(), "unsupported version 2");
+
+        let mut bad_count = data.clone();
+        bad_count[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(parse(&bad_count).unwrap_err(), "truncated");
+
+        // A name running past the end of the file
+        let mut bad_name = data.clone();
+        bad_name[HEADER_LEN + 8] = 255;
+        assert_eq!(parse(&bad_name).unwrap_err(), "truncated");
+
+        let mut extra = data.clone();
+        extra.push(0);
+        assert_eq!(parse(&extra).unwrap_err(), "wrong length");
+    }
+}
@@ -0,0 +1,194 @@
+//! Save states.
+//!
+//! A save state is a header written by the wrapper, holding its own state
+//! such as the logic update count and the rng() state, followed by the core
+//! state written by serialize_core_state(). Everything is little endian, so
+//! states load on any platform. Rewind and the frontend's save states share
+//! the format.
+
+use super::input;
+use super::notify::notify;
+use super::rng::{self, Rng};
+
+const STATE_MAGIC: &[u8; 4] = b"RLST";
+const STATE_VERSION: u32 = 2;
+const HEADER_LEN: usize = 40;
+
+/// Writes core state into a save state buffer.
+pub struct StateWriter<'a>
+{
+    data: &'a mut [u8],
+    pos: usize,
+    sections: Option<&'a mut Vec<(usize, &'static str)>>,
+}
+
+impl<'a> StateWriter<'a>
+{
+    pub fn new(data: &'a mut [u8]) -> StateWriter<'a>
+    {
+        StateWriter { data, pos: 0, sections: None }
+    }
+
+    /// Names the state written from here on, so the determinism check can
+    /// say which part of the state differs. Optional.
+    pub fn section(&mut self, name: &'static str)
+    {
+        if let Some(sections) = &mut self.sections {
+            sections.push((self.pos, name));
+        }
+    }
+
+    /// Bytes written so far.
+    pub fn len(&self) -> usize
+    {
+        self.pos
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.pos == 0
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8])
+    {
+        let end = self.pos + bytes.len();
+        assert!(end <= self.data.len(),
+                "Save state is larger than get_serialize_size() allows");
+        self.data[self.pos..end].copy_from_slice(bytes);
+        self.pos = end;
+    }
+
+    pub fn write_u8(&mut self, value: u8) { self.write_bytes(&[value]); }
+    pub fn write_bool(&mut self, value: bool) { self.write_u8(value as u8); }
+    pub fn write_u16(&mut self, value: u16) { self.write_bytes(&value.to_le_bytes()); }
+    pub fn write_u32(&mut self, value: u32) { self.write_bytes(&value.to_le_bytes()); }
+    pub fn write_u64(&mut self, value: u64) { self.write_bytes(&value.to_le_bytes()); }
+    pub fn write_i16(&mut self, value: i16) { self.write_bytes(&value.to_le_bytes()); }
+    pub fn write_i32(&mut self, value: i32) { self.write_bytes(&value.to_le_bytes()); }
+    pub fn write_i64(&mut self, value: i64) { self.write_bytes(&value.to_le_bytes()); }
+}
+
+/// Reads core state back from a save state buffer.
+pub struct StateReader<'a>
+{
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a>
+{
+    pub fn new(data: &'a [u8]) -> StateReader<'a>
+    {
+        StateReader { data, pos: 0 }
+    }
+
+    /// Bytes read so far.
+    pub fn len(&self) -> usize
+    {
+        self.pos
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.pos == 0
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> &'a [u8]
+    {
+        let end = self.pos + len;
+        assert!(end <= self.data.len(),
+                "Save state is smaller than unserialize_core_state() reads");
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        bytes
+    }
+
+    fn read_array<const N: usize>(&mut self) -> [u8; N]
+    {
+        self.read_bytes(N).try_into().unwrap()
+    }
+
+    pub fn read_u8(&mut self) -> u8 { self.read_bytes(1)[0] }
+    pub fn read_bool(&mut self) -> bool { self.read_u8() != 0 }
+    pub fn read_u16(&mut self) -> u16 { u16::from_le_bytes(self.read_array()) }
+    pub fn read_u32(&mut self) -> u32 { u32::from_le_bytes(self.read_array()) }
+    pub fn read_u64(&mut self) -> u64 { u64::from_le_bytes(self.read_array()) }
+    pub fn read_i16(&mut self) -> i16 { i16::from_le_bytes(self.read_array()) }
+    pub fn read_i32(&mut self) -> i32 { i32::from_le_bytes(self.read_array()) }
+    pub fn read_i64(&mut self) -> i64 { i64::from_le_bytes(self.read_array()) }
+}
+
+/// Size of a save state, header included.
+pub fn state_size() -> usize
+{
+    HEADER_LEN + super::super::get_serialize_size()
+}
+
+/// Saves the wrapper and core state. Fails if data is too small.
+pub fn save(data: &mut [u8]) -> bool
+{
+    let size = state_size();
+    if data.len() < size {
+        log_error!("Save state buffer of {} bytes is too small, need {}", data.len(), size);
+        return false;
+    }
+    write_state(StateWriter::new(&mut data[..size]));
+    true
+}
+
+fn write_state(mut writer: StateWriter)
+{
+    let time = input::tick_time();
+    writer.section("save state header");
+    writer.write_bytes(STATE_MAGIC);
+    writer.write_u32(STATE_VERSION);
+    writer.write_u64(time.tick);
+    writer.write_u64(time.input_tick);
+    rng::rng().get().serialize(&mut writer);
+    writer.section("core state");
+    super::super::serialize_core_state(&mut writer);
+}
+
+/// Saves the state to a new buffer, along with the offsets of the sections
+/// named by StateWriter::section().
+pub fn save_sections() -> (Vec<u8>, Vec<(usize, &'static str)>)
+{
+    let mut data = vec![0; state_size()];
+    let mut sections = Vec::new();
+    write_state(StateWriter { data: &mut data, pos: 0, sections: Some(&mut sections) });
+    (data, sections)
+}
+
+/// Saves the wrapper and core state to a new buffer.
+pub fn save_vec() -> Vec<u8>
+{
+    let mut data = vec![0; state_size()];
+    save(&mut data);
+    data
+}
+
+/// Restores a state written by save(). Fails, leaving the current state
+/// alone, if the data isn't a save state from this core.
+pub fn load(data: &[u8]) -> bool
+{
+    let size = state_size();
+    if data.len() < size || &data[..4] != STATE_MAGIC {
+        log_error!("Not a save state for this core");
+        return false;
+    }
+
+    let mut reader = StateReader::new(&data[..size]);
+    reader.read_bytes(4);
+    let version = reader.read_u32();
+    if version != STATE_VERSION {
+        log_error!("Unsupported save state version {}", version);
+        notify("Save state is from another version of the core", 3.0);
+        return false;
+    }
+    let tick = reader.read_u64();
+    let input_tick = reader.read_u64();
+    input::set_ticks(tick, input_tick);
+    rng::rng().set(Rng::unserialize(&mut reader));
+    super::super::unserialize_core_state(&mut reader);
+    true
+}
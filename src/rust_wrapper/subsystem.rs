@@ -0,0 +1,166 @@
+//! Subsystems, ways of loading several pieces of content together.
+//!
+//! The core lists its subsystems in SUBSYSTEMS, such as a base game with an
+//! expansion, or two games linked for two players. They are passed to the
+//! frontend with RETRO_ENVIRONMENT_SET_SUBSYSTEM_INFO in
+//! retro_set_environment(), and the frontend loads one through
+//! retro_load_game_special(), which hands every piece of content to
+//! core_load_subsystem() along with the part of the subsystem it fills.
+
+use core::ffi::{c_char, c_uint, c_void};
+use std::ffi::CStr;
+use std::ptr;
+use std::slice;
+use std::sync::OnceLock;
+
+use super::{environment, movie, RetroString};
+use super::libretro::*;
+
+/// A subsystem the frontend can load content with.
+pub struct Subsystem
+{
+    /// Name shown to the player, such as "Base game and expansion".
+    pub desc: &'static str,
+    /// Short name of lowercase letters a to z, such as "expansion", for
+    /// command lines.
+    pub ident: &'static str,
+    /// Number passed to retro_load_game_special(), unique to the subsystem.
+    pub id: u32,
+    /// The pieces of content loaded together, the most important first,
+    /// which the frontend names save files after.
+    pub content: &'static [SubsystemContent],
+}
+
+/// One piece of content of a subsystem.
+pub struct SubsystemContent
+{
+    /// What the content is, such as "Expansion".
+    pub desc: &'static str,
+    /// Extensions of the content, separated by pipes, like VALID_EXTENSIONS.
+    pub extensions: &'static str,
+    /// Can the subsystem be loaded without this content?
+    pub required: bool,
+    /// Should the frontend only pass the path of the content, without
+    /// loading it?
+    pub need_fullpath: bool,
+}
+
+/// A piece of content loaded for a subsystem.
+pub struct Content<'a>
+{
+    /// The part of the subsystem the content fills.
+    pub kind: &'static SubsystemContent,
+    /// Path of the content, if the frontend has one.
+    pub path: Option<String>,
+    /// The content itself, empty if the frontend only passed the path or left
+    /// out optional content. Only valid during core_load_subsystem().
+    pub data: &'a [u8],
+}
+
+impl Content<'_>
+{
+    /// Was the content given, rather than left out as optional?
+    pub fn is_present(&self) -> bool
+    {
+        self.path.is_some() || !self.data.is_empty()
+    }
+}
+
+/// Passes SUBSYSTEMS to the frontend, if there are any. The tables are built
+/// once and kept, as the frontend may hold on to them.
+pub fn register()
+{
+    use super::super::SUBSYSTEMS;
+    static INFO: OnceLock<usize> = OnceLock::new();
+
+    if SUBSYSTEMS.is_empty() {
+        return;
+    }
+    let info = *INFO.get_or_init(|| Box::leak(build_info()).as_ptr() as usize);
+    environment(RETRO_ENVIRONMENT_SET_SUBSYSTEM_INFO, info as *mut c_void);
+}
+
+/// Builds the retro_subsystem_info table, ending with a zeroed entry.
+fn build_info() -> Box<[retro_subsystem_info]>
+{
+    use super::super::SUBSYSTEMS;
+
+    // The strings and content tables are never freed
+    let leak_str = |text: &str| Box::leak(text.to_ascii_cstring().into_boxed_str()).as_ptr() as *const c_char;
+    let mut info = Vec::with_capacity(SUBSYSTEMS.len() + 1);
+    for (i, subsystem) in SUBSYSTEMS.iter().enumerate() {
+        if subsystem.ident.is_empty() || !subsystem.ident.bytes().all(|c| c.is_ascii_lowercase()) {
+            panic!("Subsystem ident must be lowercase letters a to z.");
+        }
+        if subsystem.content.is_empty() {
+            panic!("Subsystem must have content.");
+        }
+        if SUBSYSTEMS[..i].iter().any(|other| other.id == subsystem.id) {
+            panic!("Duplicate subsystem ids are forbidden.");
+        }
+        let roms: Vec<retro_subsystem_rom_info> = subsystem.content.iter()
+            .map(|content| retro_subsystem_rom_info {
+                desc: leak_str(content.desc),
+                valid_extensions: leak_str(content.extensions),
+                need_fullpath: content.need_fullpath as u8,
+                block_extract: false as u8,
+                required: content.required as u8,
+                memory: ptr::null(),
+                num_memory: 0,
+            })
+            .collect();
+        info.push(retro_subsystem_info {
+            desc: leak_str(subsystem.desc),
+            ident: leak_str(subsystem.ident),
+            roms: Box::leak(roms.into_boxed_slice()).as_ptr(),
+            num_roms: subsystem.content.len() as c_uint,
+            id: subsystem.id,
+        });
+    }
+    info.push(retro_subsystem_info {
+        desc: ptr::null(),
+        ident: ptr::null(),
+        roms: ptr::null(),
+        num_roms: 0,
+        id: 0,
+    });
+    info.into_boxed_slice()
+}
+
+/// Loads subsystem content for retro_load_game_special(), checking it
+/// matches the subsystem before passing it to core_load_subsystem().
+pub unsafe fn load(id: c_uint, info: *const retro_game_info, num: usize) -> bool
+{
+    use super::super::SUBSYSTEMS;
+
+    let Some(subsystem) = SUBSYSTEMS.iter().find(|subsystem| subsystem.id == id) else {
+        log_error!("No subsystem {}", id);
+        return false;
+    };
+    if info.is_null() || num != subsystem.content.len() {
+        log_error!("{} needs {} pieces of content, got {}",
+                   subsystem.desc, subsystem.content.len(), num);
+        return false;
+    }
+
+    let content: Vec<Content> = subsystem.content.iter()
+        .zip(slice::from_raw_parts(info, num))
+        .map(|(kind, info)| Content {
+            kind,
+            path: (!info.path.is_null())
+                .then(|| CStr::from_ptr(info.path).to_string_lossy().into_owned()),
+            data: if info.data.is_null() {
+                &[]
+            } else {
+                slice::from_raw_parts(info.data as *const u8, info.size)
+            },
+        })
+        .collect();
+    if let Some(missing) = content.iter().find(|content| content.kind.required && !content.is_present()) {
+        log_error!("{} needs {}", subsystem.desc, missing.kind.desc);
+        return false;
+    }
+    movie::set_content(content.iter().map(|content| (content.path.as_deref(), content.data)));
+    log_info!("Loading {}", subsystem.desc);
+    super::super::core_load_subsystem(subsystem, &content)
+}
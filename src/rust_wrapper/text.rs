@@ -0,0 +1,134 @@
+//! Drawing UTF-8 text with a bitmap font.
+//!
+//! The core supplies a BitmapFont listing the characters it has glyphs for,
+//! which may be any Unicode characters, so translated text from tr() shows
+//! as long as the font covers the language. draw_text() calls back for every
+//! pixel set, leaving the core to write them to frame_buf in its own colours
+//! and at its own scale.
+
+/// A fixed width font of one bit per pixel glyphs.
+pub struct BitmapFont
+{
+    /// The characters the font has glyphs for, in the order of the glyphs in
+    /// bitmap.
+    pub chars: &'static str,
+    /// Glyph size in pixels. Every character advances by width.
+    pub width: u32,
+    pub height: u32,
+    /// The glyphs, each height rows of (width + 7) / 8 bytes, with the most
+    /// significant bit of a byte the leftmost pixel.
+    pub bitmap: &'static [u8],
+}
+
+impl BitmapFont
+{
+    fn glyph_size(&self) -> usize
+    {
+        self.width.div_ceil(8) as usize * self.height as usize
+    }
+
+    /// The glyph for c, falling back to the font's replacement character or
+    /// question mark. None means draw a box.
+    fn glyph(&self, c: char) -> Option<&'static [u8]>
+    {
+        let index = [c, '\u{fffd}', '?'].iter()
+            .find_map(|&c| self.chars.chars().position(|glyph| glyph == c))?;
+        self.bitmap.get(index * self.glyph_size()..(index + 1) * self.glyph_size())
+    }
+}
+
+/// Size in pixels of text drawn with font, as the width of its longest line
+/// and the height of all its lines.
+pub fn text_size(font: &BitmapFont, text: &str) -> (u32, u32)
+{
+    let width = text.lines().map(|line| line.chars().count() as u32).max().unwrap_or(0);
+    (width * font.width, text.lines().count() as u32 * font.height)
+}
+
+/// Draws text with its top left corner at x, y, calling plot with the
+/// position of every pixel set. A \n starts a new line. Characters missing
+/// from the font are drawn as the font's replacement character or question
+/// mark, or as a box if it has neither.
+pub fn draw_text(font: &BitmapFont, text: &str, x: i32, y: i32, mut plot: impl FnMut(i32, i32))
+{
+    let (width, height) = (font.width as i32, font.height as i32);
+    let row_bytes = font.width.div_ceil(8) as usize;
+    for (line_number, line) in text.lines().enumerate() {
+        let top = y + line_number as i32 * height;
+        for (column, c) in line.chars().enumerate() {
+            let left = x + column as i32 * width;
+            if c == ' ' {
+                continue;
+            }
+            let glyph = font.glyph(c);
+            for gy in 0..height {
+                for gx in 0..width {
+                    let set = match glyph {
+                        Some(glyph) => glyph[gy as usize * row_bytes + gx as usize / 8] & (0x80 >> (gx % 8)) != 0,
+                        None => gx == 0 || gy == 0 || gx == width - 1 || gy == height - 1,
+                    };
+                    if set {
+                        plot(left + gx, top + gy);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // 3x2 glyphs, one byte a row: "A" is a bar on top, "é" a dot in the
+    // middle, "?" a bar underneath
+    const FONT: BitmapFont = BitmapFont {
+        chars: "Aé?",
+        width: 3,
+        height: 2,
+        bitmap: &[0b1110_0000, 0, 0b0100_0000, 0, 0, 0b1110_0000],
+    };
+
+    fn pixels(font: &BitmapFont, text: &str, x: i32, y: i32) -> Vec<(i32, i32)>
+    {
+        let mut pixels = Vec::new();
+        draw_text(font, text, x, y, |x, y| pixels.push((x, y)));
+        pixels
+    }
+
+    #[test]
+    fn sizes()
+    {
+        assert_eq!(text_size(&FONT, ""), (0, 0));
+        assert_eq!(text_size(&FONT, "AA"), (6, 2));
+        // Characters, not bytes, and the longest line
+        assert_eq!(text_size(&FONT, "é\nAéA\n"), (9, 4));
+    }
+
+    #[test]
+    fn draws_glyphs()
+    {
+        assert_eq!(pixels(&FONT, "A", 10, 20), [(10, 20), (11, 20), (12, 20)]);
+        assert_eq!(pixels(&FONT, " é", 0, 0), [(4, 0)]);
+        assert_eq!(pixels(&FONT, "A\né", 0, 0), [(0, 0), (1, 0), (2, 0), (1, 2)]);
+    }
+
+    #[test]
+    fn missing_glyphs_fall_back()
+    {
+        // To the question mark
+        assert_eq!(pixels(&FONT, "Z", 0, 0), [(0, 1), (1, 1), (2, 1)]);
+
+        // To the replacement character ahead of the question mark
+        let replacement = BitmapFont { chars: "?\u{fffd}", ..FONT };
+        assert_eq!(pixels(&replacement, "Z", 0, 0), [(1, 0)]);
+
+        // To a box without either
+        let boxes = BitmapFont { chars: "A", ..FONT };
+        assert_eq!(pixels(&boxes, "Z", 0, 0), [(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)]);
+        let big = BitmapFont { chars: "", width: 3, height: 3, bitmap: &[] };
+        assert!(!pixels(&big, "Z", 0, 0).contains(&(1, 1)));
+        assert_eq!(pixels(&big, "Z", 0, 0).len(), 8);
+    }
+}
@@ -0,0 +1,37 @@
+//! The player's name, as the frontend knows it.
+//!
+//! Frontends let the player set a username, which cores can use to fill in
+//! name entry and high score tables. As it comes from outside the core, it is
+//! cleaned up with sanitize_name() like any other name the core shows.
+
+use core::ffi::{c_char, c_void};
+use std::ffi::CStr;
+
+use super::environment;
+use super::libretro::*;
+
+/// Longest name, in characters, that sanitize_name() keeps.
+pub const MAX_NAME_CHARS: usize = 16;
+
+/// The frontend's username, sanitized, or None if it hasn't got one.
+pub fn username() -> Option<String>
+{
+    let mut name: *const c_char = std::ptr::null();
+    if !environment(RETRO_ENVIRONMENT_GET_USERNAME, &mut name as *mut *const c_char as *mut c_void) ||
+       name.is_null() {
+        return None;
+    }
+    let name = sanitize_name(&unsafe { CStr::from_ptr(name) }.to_string_lossy());
+    (!name.is_empty()).then_some(name)
+}
+
+/// Makes a name safe to show and store: control characters such as line
+/// breaks become spaces, runs of spaces become one, and it is cut to
+/// MAX_NAME_CHARS.
+pub fn sanitize_name(name: &str) -> String
+{
+    let name: String = name.chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+    let name: String = name.split_whitespace().collect::<Vec<_>>().join(" ")
+        .chars().take(MAX_NAME_CHARS).collect();
+    name.trim_end().to_string()
+}